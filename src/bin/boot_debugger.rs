@@ -0,0 +1,171 @@
+//! Interactive debugger for Day 8 boot code.
+
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    process,
+};
+
+use aoc_2020::handheld_halting::{
+    parse, Breakpoint, Debugger, Op, Runner, Stop, TraceEntry, Watchpoint,
+};
+
+const HELP: &str = "\
+step [n]          execute n instructions (default 1)
+continue          run until a breakpoint, a watchpoint or a halt
+run-to <addr>     run until the given address is reached
+break <addr|op>   add a breakpoint on an address or an opcode
+unbreak <addr|op> remove a breakpoint
+watch [value]     stop when acc changes, or becomes the given value
+unwatch [value]   remove a watchpoint
+trace [n]         print the last n executed instructions (default 10)
+snapshot          save the current state
+restore           restore the last saved state
+print             print the current state
+quit              exit";
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("Usage: boot_debugger <input>");
+        process::exit(2);
+    });
+    let input = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        process::exit(1);
+    });
+    let code = parse(&input).unwrap_or_else(|e| {
+        eprintln!("Failed to parse {}: {}", path, e);
+        process::exit(1);
+    });
+
+    let mut debugger = Debugger::new(Runner::new(code));
+    let mut snapshot = None;
+    let stdin = io::stdin();
+    loop {
+        print!("(boot) ");
+        io::stdout().flush().expect("Failed to flush stdout");
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .expect("Failed to read stdin")
+            == 0
+        {
+            break;
+        }
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["step"] | ["s"] => step(&mut debugger, 1),
+            ["step", n] | ["s", n] => match n.parse() {
+                Ok(n) => step(&mut debugger, n),
+                Err(_) => println!("Invalid count: {}", n),
+            },
+            ["continue"] | ["c"] => {
+                let stop = debugger.cont();
+                print_stop(&debugger, stop);
+            }
+            ["run-to", address] => match address.parse() {
+                Ok(address) => {
+                    let stop = debugger.run_to(address);
+                    print_stop(&debugger, stop);
+                }
+                Err(_) => println!("Invalid address: {}", address),
+            },
+            ["break", target] | ["b", target] => match breakpoint(target) {
+                Some(b) => debugger.add_breakpoint(b),
+                None => println!("Invalid breakpoint: {}", target),
+            },
+            ["unbreak", target] => match breakpoint(target) {
+                Some(b) if debugger.remove_breakpoint(b) => {}
+                _ => println!("No such breakpoint: {}", target),
+            },
+            ["watch"] => debugger.add_watchpoint(Watchpoint::Changed),
+            ["watch", value] => match value.parse() {
+                Ok(value) => debugger.add_watchpoint(Watchpoint::Equals(value)),
+                Err(_) => println!("Invalid value: {}", value),
+            },
+            ["unwatch"] => {
+                if !debugger.remove_watchpoint(Watchpoint::Changed) {
+                    println!("No such watchpoint");
+                }
+            }
+            ["unwatch", value] => match value.parse() {
+                Ok(value) if debugger.remove_watchpoint(Watchpoint::Equals(value)) => {}
+                _ => println!("No such watchpoint: {}", value),
+            },
+            ["trace"] => print_trace(&debugger, 10),
+            ["trace", n] => match n.parse() {
+                Ok(n) => print_trace(&debugger, n),
+                Err(_) => println!("Invalid count: {}", n),
+            },
+            ["snapshot"] => snapshot = Some(debugger.snapshot()),
+            ["restore"] => match &snapshot {
+                Some(snapshot) => debugger.restore(snapshot),
+                None => println!("No snapshot saved"),
+            },
+            ["print"] | ["p"] => print_state(&debugger),
+            ["quit"] | ["q"] => break,
+            ["help"] | ["h"] => println!("{}", HELP),
+            _ => println!("Unknown command, try `help`"),
+        }
+    }
+}
+
+fn step(debugger: &mut Debugger, n: usize) {
+    for _ in 0..n {
+        match debugger.step() {
+            Ok(entry) => print_entry(&entry),
+            Err(halt) => {
+                println!("Halted: {}", halt);
+                break;
+            }
+        }
+    }
+}
+
+fn print_stop(debugger: &Debugger, stop: Stop) {
+    match stop {
+        Stop::Halted(halt) => println!("Halted: {}", halt),
+        Stop::Breakpoint(b) => println!("Breakpoint: {}", b),
+        Stop::ReachedTarget(address) => println!("Reached {}", address),
+        Stop::Watchpoint {
+            watchpoint,
+            old,
+            new,
+        } => println!("Watchpoint {}: {} -> {}", watchpoint, old, new),
+    }
+    print_state(debugger);
+}
+
+fn print_state(debugger: &Debugger) {
+    let runner = debugger.runner();
+    print!("ip {} acc {}", runner.ip, runner.acc());
+    match runner.code.get(runner.ip as usize) {
+        Some((op, arg)) if runner.ip >= 0 => println!(": {} {:+}", op, arg),
+        _ => println!(),
+    }
+}
+
+fn print_trace(debugger: &Debugger, n: usize) {
+    let trace = debugger.trace();
+    for entry in &trace[trace.len().saturating_sub(n)..] {
+        print_entry(entry);
+    }
+}
+
+fn print_entry(entry: &TraceEntry) {
+    println!(
+        "{:>5}: {} {:+} (acc {:+})",
+        entry.ip, entry.instruction.0, entry.instruction.1, entry.acc_delta
+    );
+}
+
+fn breakpoint(s: &str) -> Option<Breakpoint> {
+    match s {
+        "nop" => Some(Breakpoint::Opcode(Op::Nop)),
+        "acc" => Some(Breakpoint::Opcode(Op::Acc)),
+        "jmp" => Some(Breakpoint::Opcode(Op::Jmp)),
+        _ => s.parse().ok().map(Breakpoint::Address),
+    }
+}
@@ -0,0 +1,47 @@
+//! Compares the Day 15 implementations on a single game.
+
+use std::{env, process, time::Instant};
+
+use aoc_2020::rambunctious_recitation::{speak_numbers, speak_numbers_dense, spoken_at};
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let initial: Vec<usize> = args
+        .next()
+        .unwrap_or_else(|| "0,3,6".to_owned())
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid starting numbers: {}", e);
+            process::exit(2);
+        });
+    let turn: u32 = args
+        .next()
+        .map_or(Ok(30_000_000), |s| s.parse())
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid turn: {}", e);
+            process::exit(2);
+        });
+    if turn == 0 {
+        eprintln!(
+            "Usage: recitation_bench [numbers] [turn], with turn between 1 and {}",
+            u32::MAX
+        );
+        process::exit(2);
+    }
+
+    let start = Instant::now();
+    let hash_map = speak_numbers(initial.clone()).nth(turn as usize - 1);
+    println!("HashMap iterator: {:?} in {:?}", hash_map, start.elapsed());
+    let start = Instant::now();
+    let dense = speak_numbers_dense(initial.clone(), turn).nth(turn as usize - 1);
+    println!("Dense iterator:   {:?} in {:?}", dense, start.elapsed());
+    let start = Instant::now();
+    let direct = spoken_at(&initial, turn as usize);
+    println!("Direct jump:      {:?} in {:?}", direct, start.elapsed());
+    assert!(
+        hash_map == dense && dense == direct,
+        "Implementations disagree"
+    );
+}
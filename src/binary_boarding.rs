@@ -1,82 +1,260 @@
 //! Day 5
 
+use std::{fmt, ops::Range};
+
+use bitvec::prelude::*;
+
 trait Solution {
-    fn part_1(&self) -> u16;
-    fn part_2(&self) -> u16;
+    fn part_1(&self) -> u32;
+    fn part_2(&self) -> u32;
 }
 impl Solution for str {
-    fn part_1(&self) -> u16 {
-        parsers::input(self)
+    fn part_1(&self) -> u32 {
+        let geometry = PlaneGeometry::default();
+        parsers::input(geometry)(self)
             .expect("Failed to parse the input")
             .into_iter()
-            .map(|pass| pass.id())
+            .map(|pass| pass.id(&geometry))
             .max()
             .expect("Empty input")
     }
-    fn part_2(&self) -> u16 {
-        let mut ids = parsers::input(self)
-            .expect("Failed to parse the input")
-            .into_iter()
-            .map(|pass| pass.id())
-            .collect::<Vec<_>>();
-        ids.sort();
-        ids.iter()
-            .zip(ids.iter().skip(1))
-            .find_map(|(&a, &b)| if b == a + 2 { Some(a + 1) } else { None })
-            .expect("Seat not found")
+    fn part_2(&self) -> u32 {
+        let geometry = PlaneGeometry::default();
+        let occupancy = Occupancy::new(
+            geometry,
+            parsers::input(geometry)(self).expect("Failed to parse the input"),
+        );
+        let seat = occupancy.candidates().next().expect("Seat not found");
+        seat.id(&geometry)
     }
 }
 
+pub type Seat = (u16, u16);
+
 pub trait Pass {
-    fn id(&self) -> u16;
+    fn id(&self, geometry: &PlaneGeometry) -> u32;
+}
+impl Pass for Seat {
+    fn id(&self, geometry: &PlaneGeometry) -> u32 {
+        self.0 as u32 * geometry.columns as u32 + self.1 as u32
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PlaneGeometry {
+    rows: u16,
+    columns: u16,
+}
+
+impl PlaneGeometry {
+    pub fn new(rows: u16, columns: u16) -> Option<Self> {
+        if rows.is_power_of_two() && columns.is_power_of_two() {
+            Some(Self { rows, columns })
+        } else {
+            None
+        }
+    }
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+    pub fn columns(&self) -> u16 {
+        self.columns
+    }
+    pub fn n_seats(&self) -> u32 {
+        self.rows as u32 * self.columns as u32
+    }
+    fn row_bits(&self) -> u32 {
+        self.rows.trailing_zeros()
+    }
+    fn col_bits(&self) -> u32 {
+        self.columns.trailing_zeros()
+    }
+
+    pub fn contains(&self, (row, col): Seat) -> bool {
+        row < self.rows && col < self.columns
+    }
+    pub fn seat(&self, id: u32) -> Option<Seat> {
+        if id < self.n_seats() {
+            Some((
+                (id / self.columns as u32) as u16,
+                (id % self.columns as u32) as u16,
+            ))
+        } else {
+            None
+        }
+    }
+    pub fn encode(&self, (row, col): Seat) -> Option<String> {
+        if self.contains((row, col)) {
+            Some(
+                encode_bits(row, self.row_bits(), 'F', 'B')
+                    + &encode_bits(col, self.col_bits(), 'L', 'R'),
+            )
+        } else {
+            None
+        }
+    }
+    pub fn encode_id(&self, id: u32) -> Option<String> {
+        self.seat(id).and_then(|seat| self.encode(seat))
+    }
 }
-impl Pass for (u8, u8) {
-    fn id(&self) -> u16 {
-        self.0 as u16 * 8 + self.1 as u16
+impl Default for PlaneGeometry {
+    fn default() -> Self {
+        Self {
+            rows: 128,
+            columns: 8,
+        }
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Occupancy {
+    geometry: PlaneGeometry,
+    occupied: BitVec,
+    duplicates: Vec<Seat>,
+    outside: Vec<Seat>,
+}
+
+impl Occupancy {
+    pub fn new(geometry: PlaneGeometry, passes: impl IntoIterator<Item = Seat>) -> Self {
+        let mut occupied = bitvec![0; geometry.n_seats() as usize];
+        let mut duplicated = bitvec![0; geometry.n_seats() as usize];
+        let mut duplicates = Vec::new();
+        let mut outside = Vec::new();
+        for seat in passes {
+            if !geometry.contains(seat) {
+                outside.push(seat);
+                continue;
+            }
+            let id = seat.id(&geometry) as usize;
+            if occupied[id] && !duplicated[id] {
+                duplicated.set(id, true);
+                duplicates.push(seat);
+            }
+            occupied.set(id, true);
+        }
+        Self {
+            geometry,
+            occupied,
+            duplicates,
+            outside,
+        }
+    }
+
+    pub fn is_occupied(&self, seat: Seat) -> bool {
+        self.geometry.contains(seat) && self.occupied[seat.id(&self.geometry) as usize]
+    }
+    // Seats scanned more than once, each listed once.
+    pub fn duplicates(&self) -> &[Seat] {
+        &self.duplicates
+    }
+    // Seats that don't exist in the plane, which are otherwise ignored.
+    pub fn outside(&self) -> &[Seat] {
+        &self.outside
+    }
+    pub fn empty_seats(&self) -> impl Iterator<Item = Seat> + '_ {
+        self.occupied
+            .iter()
+            .enumerate()
+            .filter(|(_, occupied)| !**occupied)
+            .filter_map(move |(id, _)| self.geometry.seat(id as u32))
+    }
+    // Rows before the first and after the last occupied seat.
+    pub fn missing_front_rows(&self) -> Range<u16> {
+        0..self.first_id().map_or(self.geometry.rows, |id| {
+            (id / self.geometry.columns as usize) as u16
+        })
+    }
+    pub fn missing_back_rows(&self) -> Range<u16> {
+        self.last_id().map_or(self.geometry.rows, |id| {
+            (id / self.geometry.columns as usize) as u16 + 1
+        })..self.geometry.rows
+    }
+    pub fn gaps(&self) -> impl Iterator<Item = Seat> + '_ {
+        let (front, back) = (self.missing_front_rows(), self.missing_back_rows());
+        self.empty_seats()
+            .filter(move |(row, _)| !front.contains(row) && !back.contains(row))
+    }
+    // Empty seats whose neighbors by id are both occupied.
+    pub fn candidates(&self) -> impl Iterator<Item = Seat> + '_ {
+        self.occupied
+            .windows(3)
+            .enumerate()
+            .filter(|(_, w)| w[0] && !w[1] && w[2])
+            .filter_map(move |(id, _)| self.geometry.seat(id as u32 + 1))
+    }
+
+    fn first_id(&self) -> Option<usize> {
+        self.occupied.iter().position(|occupied| *occupied)
+    }
+    fn last_id(&self) -> Option<usize> {
+        self.occupied.iter().rposition(|occupied| *occupied)
+    }
+}
+
+impl fmt::Display for Occupancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let candidates: Vec<_> = self.candidates().collect();
+        for row in 0..self.geometry.rows {
+            if row > 0 {
+                writeln!(f)?;
+            }
+            for col in 0..self.geometry.columns {
+                let c = if self.is_occupied((row, col)) {
+                    '#'
+                } else if candidates.contains(&(row, col)) {
+                    'X'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encode_bits(v: u16, n_bits: u32, zero: char, one: char) -> String {
+    (0..n_bits)
+        .rev()
+        .map(|i| if v & (1 << i) != 0 { one } else { zero })
+        .collect()
+}
+
 mod parsers {
     use nom::{
-        character::complete::{line_ending, one_of},
+        branch::alt,
+        character::complete::{char, line_ending},
+        combinator::value,
         error::Error,
         multi::separated_list0,
-        sequence::pair,
         IResult,
     };
 
     use crate::parsers::finished_parser;
 
-    use super::Pass;
+    use super::{PlaneGeometry, Seat};
 
-    pub fn input(s: &str) -> Result<Vec<impl Pass>, Error<&str>> {
-        finished_parser(separated_list0(line_ending, pass))(s)
+    pub fn input(geometry: PlaneGeometry) -> impl FnMut(&str) -> Result<Vec<Seat>, Error<&str>> {
+        move |s| finished_parser(separated_list0(line_ending, pass(geometry)))(s)
     }
-    pub fn pass(s: &str) -> IResult<&str, (u8, u8)> {
-        pair(row, col)(s)
+    pub fn pass(geometry: PlaneGeometry) -> impl Fn(&str) -> IResult<&str, Seat> {
+        move |s| {
+            let (s, row) = bits(s, geometry.row_bits(), 'F', 'B')?;
+            let (s, col) = bits(s, geometry.col_bits(), 'L', 'R')?;
+            Ok((s, (row, col)))
+        }
     }
 
-    fn row(mut s: &str) -> IResult<&str, u8> {
-        let mut row = 0;
-        for i in (0..7).rev() {
-            let (s1, b) = one_of("FB")(s)?;
-            if b == 'B' {
-                row |= 1 << i;
-            }
-            s = s1;
-        }
-        Ok((s, row))
-    }
-    fn col(mut s: &str) -> IResult<&str, u8> {
-        let mut col = 0;
-        for i in (0..3).rev() {
-            let (s1, b) = one_of("LR")(s)?;
-            if b == 'R' {
-                col |= 1 << i;
+    fn bits(mut s: &str, n_bits: u32, zero: char, one: char) -> IResult<&str, u16> {
+        let mut v = 0;
+        for i in (0..n_bits).rev() {
+            let (s1, b) = alt((value(false, char(zero)), value(true, char(one))))(s)?;
+            if b {
+                v |= 1 << i;
             }
             s = s1;
         }
-        Ok((s, col))
+        Ok((s, v))
     }
 }
 
@@ -86,14 +264,16 @@ mod tests {
 
     #[test]
     fn example_1() {
-        assert_eq!(parsers::pass("FBFBBFFRLR"), Ok(("", (44, 5))));
-        assert_eq!((44, 5).id(), 357);
+        let geometry = PlaneGeometry::default();
+        assert_eq!(parsers::pass(geometry)("FBFBBFFRLR"), Ok(("", (44, 5))));
+        assert_eq!((44, 5).id(&geometry), 357);
     }
 
     #[test]
     fn example_2() {
+        let geometry = PlaneGeometry::default();
         assert_eq!(
-            parsers::input(
+            parsers::input(geometry)(
                 "\
 BFFFBBFRRR
 FFFBBBFRRR
@@ -101,7 +281,7 @@ BBFFBBFRLL"
             )
             .unwrap()
             .iter()
-            .map(|p| p.id())
+            .map(|p| p.id(&geometry))
             .collect::<Vec<_>>(),
             vec![567, 119, 820]
         );
@@ -116,4 +296,121 @@ BBFFBBFRLL"
     fn part_2() {
         assert_eq!(include_str!("inputs/day_5").part_2(), 599);
     }
+
+    #[test]
+    fn occupancy() {
+        let geometry = PlaneGeometry::new(8, 4).unwrap();
+        let occupancy = Occupancy::new(
+            geometry,
+            vec![
+                (2, 2),
+                (2, 3),
+                (3, 0),
+                (3, 2),
+                (3, 3),
+                (4, 0),
+                (4, 1),
+                (4, 3),
+                (5, 0),
+                (4, 3),
+            ],
+        );
+        assert_eq!(occupancy.duplicates(), &[(4, 3)]);
+        assert_eq!(occupancy.missing_front_rows(), 0..2);
+        assert_eq!(occupancy.missing_back_rows(), 6..8);
+        assert_eq!(occupancy.empty_seats().count(), 32 - 9);
+        assert_eq!(
+            occupancy.gaps().collect::<Vec<_>>(),
+            vec![(2, 0), (2, 1), (3, 1), (4, 2), (5, 1), (5, 2), (5, 3)]
+        );
+        assert_eq!(
+            occupancy.candidates().collect::<Vec<_>>(),
+            vec![(3, 1), (4, 2)]
+        );
+        assert_eq!(
+            occupancy.to_string(),
+            "\
+....
+....
+..##
+#X##
+##X#
+#...
+....
+...."
+        );
+    }
+
+    #[test]
+    fn empty_occupancy() {
+        let occupancy = Occupancy::new(PlaneGeometry::new(2, 2).unwrap(), vec![]);
+        assert_eq!(occupancy.missing_front_rows(), 0..2);
+        assert_eq!(occupancy.missing_back_rows(), 2..2);
+        assert_eq!(occupancy.gaps().count(), 0);
+        assert_eq!(occupancy.candidates().count(), 0);
+    }
+
+    #[test]
+    fn repeated_scans() {
+        let geometry = PlaneGeometry::new(2, 2).unwrap();
+        let occupancy = Occupancy::new(
+            geometry,
+            vec![(0, 1), (1, 1), (0, 1), (1, 1), (0, 1), (0, 1)],
+        );
+        assert_eq!(occupancy.duplicates(), &[(0, 1), (1, 1)]);
+        assert_eq!(occupancy.empty_seats().count(), 2);
+    }
+
+    #[test]
+    fn seats_outside_the_plane() {
+        let geometry = PlaneGeometry::new(2, 2).unwrap();
+        let occupancy = Occupancy::new(geometry, vec![(0, 1), (0, 2), (2, 0), (1, 0)]);
+        assert_eq!(occupancy.outside(), &[(0, 2), (2, 0)]);
+        assert!(occupancy.is_occupied((0, 1)) && occupancy.is_occupied((1, 0)));
+        assert!(!occupancy.is_occupied((0, 2)) && !occupancy.is_occupied((5, 5)));
+        assert_eq!(
+            occupancy.empty_seats().collect::<Vec<_>>(),
+            vec![(0, 0), (1, 1)]
+        );
+        assert_eq!(occupancy.to_string(), ".#\n#.");
+        assert!(!geometry.contains((0, 2)));
+    }
+
+    #[test]
+    fn geometry() {
+        assert_eq!(PlaneGeometry::new(100, 8), None);
+        assert_eq!(PlaneGeometry::new(128, 0), None);
+        let geometry = PlaneGeometry::new(4, 2).unwrap();
+        assert_eq!(parsers::pass(geometry)("BFR"), Ok(("", (2, 1))));
+        assert!(parsers::pass(geometry)("BFFR").is_err());
+        assert_eq!((2, 1).id(&geometry), 5);
+        assert_eq!(geometry.encode((2, 1)), Some("BFR".to_owned()));
+        assert_eq!(geometry.encode((4, 1)), None);
+        assert_eq!(geometry.encode_id(8), None);
+    }
+
+    #[test]
+    fn encode() {
+        let geometry = PlaneGeometry::default();
+        assert_eq!(geometry.encode((44, 5)), Some("FBFBBFFRLR".to_owned()));
+        assert_eq!(geometry.encode_id(820), Some("BBFFBBFRLL".to_owned()));
+    }
+
+    #[test]
+    fn round_trip() {
+        for geometry in [
+            PlaneGeometry::default(),
+            PlaneGeometry::new(1, 1).unwrap(),
+            PlaneGeometry::new(4, 16).unwrap(),
+        ]
+        .iter()
+        {
+            for id in 0..geometry.n_seats() {
+                let pass = geometry.encode_id(id).unwrap();
+                let (_, seat) = parsers::pass(*geometry)(&pass).unwrap();
+                assert_eq!(Some(seat), geometry.seat(id));
+                assert_eq!(seat.id(geometry), id);
+            }
+        }
+    }
 }
@@ -1,8 +1,12 @@
 //! Day 6
 
-use std::collections::HashSet;
+use std::{
+    collections::HashMap,
+    iter::FromIterator,
+    ops::{BitAnd, BitOr, BitXor, Sub},
+};
 
-use itertools::Itertools;
+use nom::error::Error;
 
 trait Solution {
     fn part_1(&self) -> usize;
@@ -10,55 +14,358 @@ trait Solution {
 }
 impl Solution for str {
     fn part_1(&self) -> usize {
-        parsers::input(self)
+        Survey::parse(self, Format::default())
             .expect("Failed to parse the input")
-            .into_iter()
-            .map(|group| unique_answers(&group).count())
+            .groups()
+            .iter()
+            .map(|group| group.anyone().len())
             .sum()
     }
     fn part_2(&self) -> usize {
-        parsers::input(self)
+        Survey::parse(self, Format::default())
             .expect("Failed to parse the input")
-            .into_iter()
-            .map(|group| {
-                if let Some(answers) = common_answers(&group) {
-                    answers.len()
-                } else {
-                    0
-                }
-            })
+            .groups()
+            .iter()
+            .map(|group| group.everyone().len())
             .sum()
     }
 }
 
-fn unique_answers<'a>(group: &'a [&str]) -> impl Iterator<Item = char> + 'a {
-    group.iter().flat_map(|answers| answers.chars()).unique()
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Alphabet {
+    // ASCII letters of either case.
+    Letters,
+    Lowercase,
+    Uppercase,
+    Digits,
+    // Unicode letters and digits.
+    Alphanumeric,
+    Chars(String),
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Format {
+    pub alphabet: Alphabet,
+    // Questions are single characters unless a separator is given, in which
+    // case they are separator-delimited words.
+    pub separator: Option<char>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct QuestionId(usize);
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Questions {
+    names: Vec<String>,
+    ids: HashMap<String, QuestionId>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default, Hash)]
+pub struct Answers {
+    words: Vec<u64>,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Group {
+    people: Vec<Answers>,
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Survey {
+    format: Format,
+    questions: Questions,
+    groups: Vec<Group>,
+}
+
+impl Alphabet {
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            Alphabet::Letters => c.is_ascii_alphabetic(),
+            Alphabet::Lowercase => c.is_ascii_lowercase(),
+            Alphabet::Uppercase => c.is_ascii_uppercase(),
+            Alphabet::Digits => c.is_ascii_digit(),
+            Alphabet::Alphanumeric => c.is_alphanumeric(),
+            Alphabet::Chars(chars) => chars.contains(c),
+        }
+    }
+}
+
+impl Format {
+    pub fn new(alphabet: Alphabet) -> Self {
+        Self {
+            alphabet,
+            separator: None,
+        }
+    }
+    pub fn separated_by(self, separator: char) -> Self {
+        Self {
+            separator: Some(separator),
+            ..self
+        }
+    }
+
+    fn questions<'a>(&self, person: &'a str) -> Vec<&'a str> {
+        match self.separator {
+            Some(separator) => person.split(separator).collect(),
+            None => person
+                .char_indices()
+                .map(|(ix, c)| &person[ix..ix + c.len_utf8()])
+                .collect(),
+        }
+    }
+}
+impl Default for Format {
+    fn default() -> Self {
+        Self::new(Alphabet::Letters)
+    }
+}
+
+impl QuestionId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
 }
-fn common_answers(group: &[&str]) -> Option<HashSet<char>> {
-    group
-        .iter()
-        .map(|answers| answers.chars().collect::<HashSet<_>>())
-        .fold1(|mut common, answers| {
-            common.retain(|a| answers.contains(a));
-            common
+
+impl Questions {
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+    pub fn get(&self, name: &str) -> Option<QuestionId> {
+        self.ids.get(name).copied()
+    }
+    pub fn name(&self, id: QuestionId) -> &str {
+        &self.names[id.0]
+    }
+    pub fn names<'a: 'b, 'b>(&'a self, answers: &'b Answers) -> impl Iterator<Item = &'a str> + 'b {
+        answers.iter().map(move |id| self.name(id))
+    }
+    pub fn all(&self) -> Answers {
+        (0..self.len()).map(QuestionId).collect()
+    }
+    pub fn intern(&mut self, name: &str) -> QuestionId {
+        if let Some(id) = self.get(name) {
+            return id;
+        }
+        let id = QuestionId(self.names.len());
+        self.names.push(name.to_owned());
+        self.ids.insert(name.to_owned(), id);
+        id
+    }
+}
+
+impl Answers {
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+    pub fn contains(&self, id: QuestionId) -> bool {
+        self.words
+            .get(id.0 / 64)
+            .is_some_and(|w| w & (1 << (id.0 % 64)) != 0)
+    }
+    pub fn insert(&mut self, id: QuestionId) {
+        if self.words.len() <= id.0 / 64 {
+            self.words.resize(id.0 / 64 + 1, 0);
+        }
+        self.words[id.0 / 64] |= 1 << (id.0 % 64);
+    }
+    pub fn iter(&self) -> impl Iterator<Item = QuestionId> + '_ {
+        self.words.iter().enumerate().flat_map(|(ix, &w)| {
+            (0..64)
+                .filter(move |bit| w & (1 << bit) != 0)
+                .map(move |bit| QuestionId(ix * 64 + bit))
         })
+    }
+    // Questions in `0..n_questions` that are not in `self`.
+    pub fn complement(&self, n_questions: usize) -> Answers {
+        &(0..n_questions).map(QuestionId).collect() - self
+    }
+
+    fn zip_with(&self, other: &Answers, f: impl Fn(u64, u64) -> u64) -> Answers {
+        let n_words = self.words.len().max(other.words.len());
+        let mut words: Vec<_> = (0..n_words)
+            .map(|ix| {
+                f(
+                    self.words.get(ix).copied().unwrap_or(0),
+                    other.words.get(ix).copied().unwrap_or(0),
+                )
+            })
+            .collect();
+        // Keep the representation canonical for equality and `is_empty`.
+        while words.last() == Some(&0) {
+            words.pop();
+        }
+        Answers { words }
+    }
+}
+impl FromIterator<QuestionId> for Answers {
+    fn from_iter<I: IntoIterator<Item = QuestionId>>(iter: I) -> Self {
+        let mut answers = Answers::default();
+        for id in iter {
+            answers.insert(id);
+        }
+        answers
+    }
+}
+impl BitOr for &Answers {
+    type Output = Answers;
+
+    fn bitor(self, rhs: &Answers) -> Answers {
+        self.zip_with(rhs, |a, b| a | b)
+    }
+}
+impl BitAnd for &Answers {
+    type Output = Answers;
+
+    fn bitand(self, rhs: &Answers) -> Answers {
+        self.zip_with(rhs, |a, b| a & b)
+    }
+}
+impl BitXor for &Answers {
+    type Output = Answers;
+
+    fn bitxor(self, rhs: &Answers) -> Answers {
+        self.zip_with(rhs, |a, b| a ^ b)
+    }
+}
+impl Sub for &Answers {
+    type Output = Answers;
+
+    fn sub(self, rhs: &Answers) -> Answers {
+        self.zip_with(rhs, |a, b| a & !b)
+    }
+}
+
+impl Group {
+    pub fn people(&self) -> &[Answers] {
+        &self.people
+    }
+    pub fn anyone(&self) -> Answers {
+        self.people
+            .iter()
+            .fold(Answers::default(), |acc, a| &acc | a)
+    }
+    pub fn everyone(&self) -> Answers {
+        match self.people.split_first() {
+            Some((first, rest)) => rest.iter().fold(first.clone(), |acc, a| &acc & a),
+            None => Answers::default(),
+        }
+    }
+    // Number of people that answered "yes" to each question, indexed by id.
+    pub fn histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        for id in self.people.iter().flat_map(Answers::iter) {
+            if histogram.len() <= id.0 {
+                histogram.resize(id.0 + 1, 0);
+            }
+            histogram[id.0] += 1;
+        }
+        histogram
+    }
+    // Questions in `0..n_questions` answered by at least `k` people.
+    pub fn at_least(&self, k: usize, n_questions: usize) -> Answers {
+        self.select(n_questions, |count| count >= k)
+    }
+    // Questions in `0..n_questions` answered by exactly `k` people, so
+    // `exactly(0, _)` holds the questions nobody answered.
+    pub fn exactly(&self, k: usize, n_questions: usize) -> Answers {
+        self.select(n_questions, |count| count == k)
+    }
+
+    fn select(&self, n_questions: usize, predicate: impl Fn(usize) -> bool) -> Answers {
+        let histogram = self.histogram();
+        (0..n_questions)
+            .filter(|&ix| predicate(histogram.get(ix).copied().unwrap_or(0)))
+            .map(QuestionId)
+            .collect()
+    }
+}
+impl FromIterator<Answers> for Group {
+    fn from_iter<I: IntoIterator<Item = Answers>>(iter: I) -> Self {
+        Group {
+            people: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Survey {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            questions: Questions::default(),
+            groups: Vec::new(),
+        }
+    }
+    pub fn parse(s: &str, format: Format) -> Result<Self, Error<&str>> {
+        let groups = parsers::input(&format)(s)?;
+        let mut survey = Self::new(format);
+        for group in groups {
+            survey.add_group(&group);
+        }
+        Ok(survey)
+    }
+
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+    pub fn questions(&self) -> &Questions {
+        &self.questions
+    }
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+    pub fn add_group(&mut self, people: &[&str]) -> &Group {
+        let format = &self.format;
+        let questions = &mut self.questions;
+        let group = people
+            .iter()
+            .map(|person| {
+                format
+                    .questions(person)
+                    .into_iter()
+                    .map(|q| questions.intern(q))
+                    .collect()
+            })
+            .collect();
+        self.groups.push(group);
+        self.groups.last().unwrap()
+    }
 }
 
 mod parsers {
     use nom::{
-        character::complete::{alpha1, line_ending},
+        character::complete::{char, line_ending, satisfy},
+        combinator::recognize,
         error::Error,
-        multi::separated_list1,
+        multi::{many1, separated_list1},
         IResult,
     };
 
     use crate::parsers::{double_line_ending, finished_parser};
 
-    pub fn input(s: &str) -> Result<Vec<Vec<&str>>, Error<&str>> {
-        finished_parser(separated_list1(double_line_ending, group))(s)
+    use super::Format;
+
+    pub fn input<'f, 'a>(
+        format: &'f Format,
+    ) -> impl FnMut(&'a str) -> Result<Vec<Vec<&'a str>>, Error<&'a str>> + 'f {
+        move |s| finished_parser(separated_list1(double_line_ending, group(format)))(s)
+    }
+    pub fn group<'f, 'a>(
+        format: &'f Format,
+    ) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<&'a str>> + 'f {
+        move |s| separated_list1(line_ending, person(format))(s)
     }
-    pub fn group(s: &str) -> IResult<&str, Vec<&str>> {
-        separated_list1(line_ending, alpha1)(s)
+    fn person<'f, 'a>(format: &'f Format) -> impl Fn(&'a str) -> IResult<&'a str, &'a str> + 'f {
+        move |s| {
+            let question = many1(satisfy(|c| format.alphabet.contains(c)));
+            match format.separator {
+                Some(separator) => {
+                    recognize(separated_list1(char(separator), recognize(question)))(s)
+                }
+                None => recognize(question)(s),
+            }
+        }
     }
 }
 
@@ -68,10 +375,14 @@ mod tests {
 
     use super::*;
 
+    fn group_of(people: &[&str]) -> Group {
+        Survey::new(Format::default()).add_group(people).clone()
+    }
+
     #[test]
     fn example_group() {
         assert_eq!(
-            parsers::group(
+            parsers::group(&Format::default())(
                 "\
 abcx
 abcy
@@ -84,7 +395,7 @@ abcz"
     #[test]
     fn example_input() {
         assert_eq!(
-            parsers::input(
+            parsers::input(&Format::default())(
                 "\
 abc
 
@@ -114,9 +425,11 @@ b"
 
     #[test]
     fn example_1() {
+        let mut survey = Survey::new(Format::default());
+        let anyone = survey.add_group(&["abcx", "abcy", "abcz"]).anyone();
         assert_equal(
-            unique_answers(&["abcx", "abcy", "abcz"]),
-            vec!['a', 'b', 'c', 'x', 'y', 'z'],
+            survey.questions().names(&anyone),
+            vec!["a", "b", "c", "x", "y", "z"],
         )
     }
 
@@ -131,7 +444,7 @@ b"
                 vec!["b"],
             ]
             .iter()
-            .map(|group| unique_answers(group).count()),
+            .map(|group| group_of(group).anyone().len()),
             vec![3, 3, 3, 1, 1],
         )
     }
@@ -152,7 +465,7 @@ b"
                 vec!["b"],
             ]
             .iter()
-            .map(|group| common_answers(group).unwrap().len()),
+            .map(|group| group_of(group).everyone().len()),
             vec![3, 0, 1, 1, 1],
         )
     }
@@ -161,4 +474,108 @@ b"
     fn part_2() {
         assert_eq!(include_str!("inputs/day_6").part_2(), 3103);
     }
+
+    #[test]
+    fn answers() {
+        let abc: Answers = (0..3).map(QuestionId).collect();
+        let bcd: Answers = (1..4).map(QuestionId).collect();
+        let ids = |answers: Answers| answers.iter().map(|id| id.index()).collect::<Vec<_>>();
+        assert_eq!(ids(&abc | &bcd), vec![0, 1, 2, 3]);
+        assert_eq!(ids(&abc & &bcd), vec![1, 2]);
+        assert_eq!(ids(&abc ^ &bcd), vec![0, 3]);
+        assert_eq!(ids(&abc - &bcd), vec![0]);
+        assert_eq!(abc.complement(26).len(), 23);
+        assert!(!abc.complement(26).contains(QuestionId(0)));
+        assert!(abc.complement(26).contains(QuestionId(25)));
+        assert!((&abc - &abc).is_empty());
+        assert_eq!(&abc & &Answers::default(), Answers::default());
+        let far: Answers = vec![QuestionId(200)].into_iter().collect();
+        assert_eq!(ids(&abc | &far), vec![0, 1, 2, 200]);
+        assert_eq!((&(&abc | &far) - &far), abc);
+    }
+
+    #[test]
+    fn quorum() {
+        let mut survey = Survey::new(Format::default());
+        let group = survey.add_group(&["abcx", "abcy", "abz", "a"]).clone();
+        survey.add_group(&["pq"]);
+        let n = survey.questions().len();
+        let names = |answers: Answers| survey.questions().names(&answers).collect::<String>();
+        assert_eq!(names(group.anyone()), "abcxyz");
+        assert_eq!(names(group.everyone()), "a");
+        assert_eq!(names(group.at_least(2, n)), "abc");
+        assert_eq!(names(group.at_least(3, n)), "ab");
+        assert_eq!(names(group.exactly(1, n)), "xyz");
+        assert_eq!(names(group.exactly(2, n)), "c");
+        assert_eq!(group.histogram(), vec![4, 3, 2, 1, 1, 1]);
+        assert_eq!(names(group.at_least(0, n)), "abcxyzpq");
+        assert_eq!(names(group.exactly(0, n)), "pq");
+        assert_eq!(group.at_least(1, n), group.anyone());
+        assert_eq!(group.exactly(0, n), group.anyone().complement(n));
+        assert_eq!(Group::default().everyone(), Answers::default());
+    }
+
+    #[test]
+    fn expressions() {
+        let mut survey = Survey::new(Format::default());
+        survey.add_group(&["abc"]);
+        survey.add_group(&["a", "b", "c"]);
+        survey.add_group(&["ab", "ac"]);
+        let groups = survey.groups();
+        let names = |answers: Answers| survey.questions().names(&answers).collect::<String>();
+        let answered_by_all = groups
+            .iter()
+            .fold(survey.questions().all(), |acc, g| &acc & &g.anyone());
+        assert_eq!(names(answered_by_all), "abc");
+        assert_eq!(names(&groups[0].everyone() & &groups[2].everyone()), "a");
+        assert_eq!(names(&groups[0].everyone() - &groups[2].everyone()), "bc");
+        assert_eq!(
+            names(
+                &groups[1].anyone()
+                    & &groups[2]
+                        .at_least(2, survey.questions().len())
+                        .complement(survey.questions().len())
+            ),
+            "bc"
+        );
+    }
+
+    #[test]
+    fn formats() {
+        let survey = Survey::parse("AB1\nB\n\nÄÖ", Format::new(Alphabet::Alphanumeric)).unwrap();
+        assert_eq!(survey.questions().len(), 5);
+        assert_eq!(survey.groups()[0].everyone().len(), 1);
+        assert_eq!(survey.questions().get("Ö"), Some(QuestionId(4)));
+        assert!(Survey::parse("ab", Format::new(Alphabet::Uppercase)).is_err());
+        assert!(Survey::parse("aB", Format::new(Alphabet::Lowercase)).is_err());
+        assert!(Survey::parse("aä", Format::new(Alphabet::Lowercase)).is_err());
+        assert!(Survey::parse("AÄ", Format::new(Alphabet::Uppercase)).is_err());
+        let survey = Survey::parse(
+            "aB
+b",
+            Format::default(),
+        )
+        .unwrap();
+        assert_eq!(survey.questions().len(), 3);
+        assert_eq!(survey.groups()[0].everyone().len(), 0);
+        assert!(Survey::parse("ä", Format::default()).is_err());
+        assert!(Survey::parse("12\n3", Format::new(Alphabet::Digits)).is_ok());
+        assert!(Survey::parse("ab", Format::new(Alphabet::Chars("abc".to_owned()))).is_ok());
+        assert!(Survey::parse("abd", Format::new(Alphabet::Chars("abc".to_owned()))).is_err());
+    }
+
+    #[test]
+    fn multi_character_questions() {
+        let format = Format::new(Alphabet::Lowercase).separated_by(',');
+        assert_eq!(
+            parsers::group(&format)("pets,cars\ncars"),
+            Ok(("", vec!["pets,cars", "cars"]))
+        );
+        let survey = Survey::parse("pets,cars\ncars\n\npets", format).unwrap();
+        let names = |answers: Answers| survey.questions().names(&answers).collect::<Vec<_>>();
+        assert_eq!(names(survey.groups()[0].anyone()), vec!["pets", "cars"]);
+        assert_eq!(names(survey.groups()[0].everyone()), vec!["cars"]);
+        assert_eq!(names(survey.groups()[1].everyone()), vec!["pets"]);
+        assert!(Survey::parse("pets,,cars", Format::default().separated_by(',')).is_err());
+    }
 }
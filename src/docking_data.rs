@@ -1,9 +1,10 @@
 //! Day 14
 
-use std::collections::HashMap;
-
-use bitvec::prelude::*;
-use itertools::Either;
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    fmt,
+};
 
 trait Solution {
     fn part_1(&self) -> u64;
@@ -11,78 +12,321 @@ trait Solution {
 }
 impl Solution for str {
     fn part_1(&self) -> u64 {
-        let mem = execute_v1(&parsers::input(self).expect("Failed to parse the input"));
-        mem.into_iter().map(|(_, v)| v).sum()
+        let mut computer = DockingComputer::new(N_BITS, Decoder::V1).unwrap();
+        computer.run(&parsers::input(N_BITS)(self).expect("Failed to parse the input"));
+        computer
+            .sum()
+            .and_then(|sum| sum.try_into().ok())
+            .expect("Sum overflows")
     }
     fn part_2(&self) -> u64 {
-        let mem = execute_v2(&parsers::input(self).expect("Failed to parse the input"));
-        mem.into_iter().map(|(_, v)| v).sum()
+        let mut computer = DockingComputer::new(N_BITS, Decoder::V2).unwrap();
+        computer.run(&parsers::input(N_BITS)(self).expect("Failed to parse the input"));
+        computer
+            .sum()
+            .and_then(|sum| sum.try_into().ok())
+            .expect("Sum overflows")
     }
 }
 
-const N_BITS: usize = 36;
+pub const N_BITS: u32 = 36;
+pub const MAX_BITS: u32 = 128;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Instruction {
+    Mask(Mask),
+    Write { address: u128, value: u128 },
+}
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Decoder {
+    // The mask applies to values.
+    V1,
+    // The mask applies to addresses, with `X` bits floating.
+    V2,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
 pub struct Mask {
-    ones: u64,
-    zeros: u64,
+    ones: u128,
+    zeros: u128,
 }
-impl Default for Mask {
-    fn default() -> Self {
-        Self { ones: 0, zeros: 0 }
+impl Mask {
+    fn get_floating_mask(&self, n_bits: u32) -> u128 {
+        !(self.ones | self.zeros) & width_mask(n_bits)
+    }
+    pub fn format(&self, n_bits: u32) -> String {
+        (0..n_bits)
+            .rev()
+            .map(|ix| match (self.ones >> ix & 1, self.zeros >> ix & 1) {
+                (1, _) => '1',
+                (_, 1) => '0',
+                _ => 'X',
+            })
+            .collect()
     }
 }
-impl Mask {
-    fn get_floating_mask(&self) -> u64 {
-        !(self.ones | self.zeros) & ((1 << N_BITS) - 1)
+
+// Empty for zero bits and full for `MAX_BITS` or more.
+fn width_mask(n_bits: u32) -> u128 {
+    u128::MAX
+        .checked_shr(MAX_BITS.saturating_sub(n_bits))
+        .unwrap_or(0)
+}
+
+// A set of addresses where the `floating` bits take every possible value.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AddressPattern {
+    fixed: u128,
+    floating: u128,
+}
+impl AddressPattern {
+    pub fn new(address: u128, mask: &Mask, n_bits: u32) -> Self {
+        let floating = mask.get_floating_mask(n_bits);
+        Self {
+            fixed: (address | mask.ones) & !floating & width_mask(n_bits),
+            floating,
+        }
+    }
+    pub fn single(address: u128) -> Self {
+        Self {
+            fixed: address,
+            floating: 0,
+        }
+    }
+    // None if every one of the 128 bits is floating.
+    pub fn n_addresses(&self) -> Option<u128> {
+        1u128.checked_shl(self.floating.count_ones())
+    }
+    pub fn contains(&self, address: u128) -> bool {
+        address & !self.floating == self.fixed
+    }
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        if (self.fixed ^ other.fixed) & !(self.floating | other.floating) != 0 {
+            return None;
+        }
+        let floating = self.floating & other.floating;
+        Some(Self {
+            fixed: (self.fixed | other.fixed) & !floating,
+            floating,
+        })
+    }
+    // Every address in the pattern, in increasing order.
+    pub fn addresses(&self) -> impl Iterator<Item = u128> {
+        let (fixed, floating) = (self.fixed, self.floating);
+        let mut next = Some(0u128);
+        std::iter::from_fn(move || {
+            let current = next?;
+            next = if current == floating {
+                None
+            } else {
+                Some(current.wrapping_sub(floating) & floating)
+            };
+            Some(fixed | current)
+        })
+    }
+    pub fn format(&self, n_bits: u32) -> String {
+        (0..n_bits)
+            .rev()
+            .map(|ix| match (self.floating >> ix & 1, self.fixed >> ix & 1) {
+                (1, _) => 'X',
+                (_, 1) => '1',
+                _ => '0',
+            })
+            .collect()
     }
 }
 
-fn execute_v1(instructions: &[Either<Mask, (u64, u64)>]) -> HashMap<u64, u64> {
-    instructions
-        .iter()
-        .fold(
-            (HashMap::new(), Mask::default()),
-            |(mut mem, mask), instruction| match instruction {
-                Either::Left(mask) => (mem, *mask),
-                Either::Right((address, value)) => {
-                    mem.insert(*address, (value & !mask.zeros) | mask.ones);
-                    (mem, mask)
-                }
-            },
-        )
-        .0
+// Memory that keeps writes as address patterns instead of expanding them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SparseMemory {
+    writes: Vec<(AddressPattern, u128)>,
 }
-fn execute_v2(instructions: &[Either<Mask, (u64, u64)>]) -> HashMap<u64, u64> {
-    instructions
+impl SparseMemory {
+    pub fn write(&mut self, pattern: AddressPattern, value: u128) {
+        self.writes
+            .retain(|(old, _)| old.intersection(&pattern) != Some(*old));
+        self.writes.push((pattern, value));
+    }
+    pub fn get(&self, address: u128) -> Option<u128> {
+        self.writes
+            .iter()
+            .rev()
+            .find(|(pattern, _)| pattern.contains(address))
+            .map(|&(_, value)| value)
+    }
+    // None if the sum doesn't fit in 128 bits.
+    pub fn sum(&self) -> Option<u128> {
+        let patterns: Vec<_> = self.writes.iter().map(|&(pattern, _)| pattern).collect();
+        self.writes
+            .iter()
+            .enumerate()
+            .try_fold(0u128, |sum, (ix, &(pattern, value))| {
+                let total = match value {
+                    0 => 0,
+                    _ => value.checked_mul(uncovered(&pattern, &patterns[ix + 1..])?)?,
+                };
+                sum.checked_add(total)
+            })
+    }
+    // Every written address with its value. Expands all the patterns, so only
+    // practical when few bits are floating.
+    pub fn dump(&self) -> BTreeMap<u128, u128> {
+        let mut dump = BTreeMap::new();
+        for (pattern, value) in self.writes.iter().rev() {
+            for address in pattern.addresses() {
+                dump.entry(address).or_insert(*value);
+            }
+        }
+        dump
+    }
+}
+// Number of addresses in `pattern` not overwritten by any of `later`: each
+// later write only removes what the ones after it didn't already remove.
+// None if the count doesn't fit in 128 bits.
+fn uncovered(pattern: &AddressPattern, later: &[AddressPattern]) -> Option<u128> {
+    let overlaps: Vec<_> = later
         .iter()
-        .fold(
-            (HashMap::new(), Mask::default()),
-            |(mut mem, mask), instruction| match instruction {
-                Either::Left(mask) => (mem, *mask),
-                Either::Right((address, value)) => {
-                    let floating_mask = mask.get_floating_mask();
-                    let base_address = (address & !floating_mask) | mask.ones;
-                    for fluctuation_ix in 0u32..1 << floating_mask.count_ones() {
-                        let fluctuation: u64 = floating_mask
-                            .view_bits::<Lsb0>()
-                            .iter()
-                            .enumerate()
-                            .filter_map(|(ix, &b)| if b { Some(ix) } else { None })
-                            .zip(fluctuation_ix.view_bits::<Lsb0>())
-                            .filter_map(|(ix, &b)| if b { Some(1u64 << ix) } else { None })
-                            .sum();
-                        mem.insert(base_address | fluctuation, *value);
-                    }
-                    (mem, mask)
+        .filter_map(|other| pattern.intersection(other))
+        .collect();
+    let covered = (0..overlaps.len()).try_fold(0u128, |covered, ix| {
+        covered.checked_add(uncovered(&overlaps[ix], &overlaps[ix + 1..])?)
+    })?;
+    Some(pattern.n_addresses()? - covered)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TraceEntry {
+    n_bits: u32,
+    pub mask: Mask,
+    pub written: Option<(AddressPattern, u128)>,
+}
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.written {
+            None => write!(f, "mask = {}", self.mask.format(self.n_bits)),
+            Some((pattern, value)) => write!(
+                f,
+                "mem[{}] = {} (mask {}, {} addresses)",
+                pattern.format(self.n_bits),
+                value,
+                self.mask.format(self.n_bits),
+                pattern.n_addresses().map_or_else(
+                    || format!("2^{}", MAX_BITS),
+                    |n_addresses| n_addresses.to_string()
+                )
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DockingComputer {
+    n_bits: u32,
+    decoder: Decoder,
+    mask: Mask,
+    memory: SparseMemory,
+}
+impl DockingComputer {
+    pub fn new(n_bits: u32, decoder: Decoder) -> Option<Self> {
+        if (1..=MAX_BITS).contains(&n_bits) {
+            Some(Self {
+                n_bits,
+                decoder,
+                mask: Mask::default(),
+                memory: SparseMemory::default(),
+            })
+        } else {
+            None
+        }
+    }
+    pub fn n_bits(&self) -> u32 {
+        self.n_bits
+    }
+    pub fn decoder(&self) -> Decoder {
+        self.decoder
+    }
+    pub fn mask(&self) -> Mask {
+        self.mask
+    }
+    pub fn memory(&self) -> &SparseMemory {
+        &self.memory
+    }
+
+    pub fn execute(&mut self, instruction: &Instruction) -> TraceEntry {
+        let written = match *instruction {
+            Instruction::Mask(mask) => {
+                self.mask = mask;
+                None
+            }
+            Instruction::Write { address, value } => {
+                let width = width_mask(self.n_bits);
+                let write = match self.decoder {
+                    Decoder::V1 => (
+                        AddressPattern::single(address & width),
+                        (value & !self.mask.zeros | self.mask.ones) & width,
+                    ),
+                    Decoder::V2 => (
+                        AddressPattern::new(address, &self.mask, self.n_bits),
+                        value & width,
+                    ),
+                };
+                self.memory.write(write.0, write.1);
+                Some(write)
+            }
+        };
+        TraceEntry {
+            n_bits: self.n_bits,
+            mask: self.mask,
+            written,
+        }
+    }
+    pub fn run(&mut self, instructions: &[Instruction]) -> Vec<TraceEntry> {
+        instructions
+            .iter()
+            .map(|instruction| self.execute(instruction))
+            .collect()
+    }
+    pub fn get(&self, address: u128) -> Option<u128> {
+        self.memory.get(address)
+    }
+    pub fn sum(&self) -> Option<u128> {
+        self.memory.sum()
+    }
+    pub fn dump(&self) -> BTreeMap<u128, u128> {
+        self.memory.dump()
+    }
+}
+
+// Reference implementation of the v2 decoder that expands every pattern.
+fn execute_v2(instructions: &[Instruction], n_bits: u32) -> HashMap<u128, u128> {
+    let mut mem = HashMap::new();
+    let mut mask = Mask::default();
+    for instruction in instructions {
+        match *instruction {
+            Instruction::Mask(new_mask) => mask = new_mask,
+            Instruction::Write { address, value } => {
+                let floating_mask = mask.get_floating_mask(n_bits);
+                let base_address = (address & !floating_mask) | mask.ones;
+                let floating_bits: Vec<_> = (0..n_bits)
+                    .filter(|ix| floating_mask >> ix & 1 == 1)
+                    .collect();
+                for fluctuation_ix in 0u128..1 << floating_bits.len() {
+                    let fluctuation: u128 = floating_bits
+                        .iter()
+                        .enumerate()
+                        .filter(|(bit, _)| fluctuation_ix >> bit & 1 == 1)
+                        .map(|(_, ix)| 1 << ix)
+                        .sum();
+                    mem.insert(base_address | fluctuation, value);
                 }
-            },
-        )
-        .0
+            }
+        }
+    }
+    mem
 }
 
 mod parsers {
-    use itertools::Either;
     use nom::{
         branch::alt,
         bytes::complete::tag,
@@ -95,24 +339,31 @@ mod parsers {
 
     use crate::parsers::{finished_parser, integer};
 
-    use super::{Mask, N_BITS};
+    use super::{Instruction, Mask};
 
-    pub fn input(s: &str) -> Result<Vec<Either<Mask, (u64, u64)>>, Error<&str>> {
-        finished_parser(separated_list0(
-            line_ending,
-            alt((
-                map(mask_line, |mask| Either::Left(mask)),
-                map(mem_line, |mem| Either::Right(mem)),
-            )),
-        ))(s)
+    pub fn input(n_bits: u32) -> impl FnMut(&str) -> Result<Vec<Instruction>, Error<&str>> {
+        move |s| {
+            finished_parser(separated_list0(
+                line_ending,
+                alt((
+                    map(mask_line(n_bits), Instruction::Mask),
+                    map(mem_line, |(address, value)| Instruction::Write {
+                        address,
+                        value,
+                    }),
+                )),
+            ))(s)
+        }
     }
 
-    fn mask_line(s: &str) -> IResult<&str, Mask> {
-        let (s, _) = tag("mask = ")(s)?;
-        mask(s)
+    fn mask_line(n_bits: u32) -> impl FnMut(&str) -> IResult<&str, Mask> {
+        move |s| {
+            let (s, _) = tag("mask = ")(s)?;
+            mask(s, n_bits)
+        }
     }
-    fn mask(s: &str) -> IResult<&str, Mask> {
-        let (s, raw) = many_m_n(1, N_BITS, one_of("X01"))(s)?;
+    fn mask(s: &str, n_bits: u32) -> IResult<&str, Mask> {
+        let (s, raw) = many_m_n(1, n_bits as usize, one_of("X01"))(s)?;
         Ok((
             s,
             Mask {
@@ -121,7 +372,7 @@ mod parsers {
             },
         ))
     }
-    fn mask_for(x: &char, chars: &[char]) -> u64 {
+    fn mask_for(x: &char, chars: &[char]) -> u128 {
         chars
             .iter()
             .rev()
@@ -130,7 +381,7 @@ mod parsers {
             .sum()
     }
 
-    fn mem_line(s: &str) -> IResult<&str, (u64, u64)> {
+    fn mem_line(s: &str) -> IResult<&str, (u128, u128)> {
         let (s, _) = tag("mem[")(s)?;
         let (s, address) = integer(s)?;
         let (s, _) = tag("] = ")(s)?;
@@ -146,7 +397,7 @@ mod tests {
     #[test]
     fn example_input() {
         assert_eq!(
-            parsers::input(
+            parsers::input(N_BITS)(
                 "\
 mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
 mem[8] = 11
@@ -154,27 +405,47 @@ mem[7] = 101
 mem[8] = 0"
             ),
             Ok(vec![
-                Either::Left(Mask { ones: 64, zeros: 2 }),
-                Either::Right((8, 11)),
-                Either::Right((7, 101)),
-                Either::Right((8, 0)),
+                Instruction::Mask(Mask { ones: 64, zeros: 2 }),
+                Instruction::Write {
+                    address: 8,
+                    value: 11
+                },
+                Instruction::Write {
+                    address: 7,
+                    value: 101
+                },
+                Instruction::Write {
+                    address: 8,
+                    value: 0
+                },
             ])
         );
     }
 
     #[test]
     fn example_1() {
+        let mut computer = DockingComputer::new(N_BITS, Decoder::V1).unwrap();
+        computer.run(&[
+            Instruction::Mask(Mask { ones: 64, zeros: 2 }),
+            Instruction::Write {
+                address: 8,
+                value: 11,
+            },
+            Instruction::Write {
+                address: 7,
+                value: 101,
+            },
+            Instruction::Write {
+                address: 8,
+                value: 0,
+            },
+        ]);
         assert_eq!(
-            execute_v1(&[
-                Either::Left(Mask { ones: 64, zeros: 2 }),
-                Either::Right((8, 11)),
-                Either::Right((7, 101)),
-                Either::Right((8, 0)),
-            ]),
+            computer.dump(),
             [(7, 101), (8, 64)]
                 .iter()
                 .copied()
-                .collect::<HashMap<_, _>>()
+                .collect::<BTreeMap<_, _>>()
         );
     }
 
@@ -187,14 +458,15 @@ mem[8] = 0"
     fn example_2() {
         assert_eq!(
             execute_v2(
-                &parsers::input(
+                &parsers::input(N_BITS)(
                     "\
 mask = 000000000000000000000000000000X1001X
 mem[42] = 100
 mask = 00000000000000000000000000000000X0XX
 mem[26] = 1"
                 )
-                .unwrap()
+                .unwrap(),
+                N_BITS
             ),
             [
                 (26, 100),
@@ -220,4 +492,160 @@ mem[26] = 1"
     fn part_2() {
         assert_eq!(include_str!("inputs/day_14").part_2(), 3_348_493_585_827);
     }
+
+    #[test]
+    fn sparse_v2() {
+        let instructions = parsers::input(N_BITS)(include_str!("inputs/day_14")).unwrap();
+        for n in (0..instructions.len()).step_by(50) {
+            let expanded = execute_v2(&instructions[..n], N_BITS);
+            let mut computer = DockingComputer::new(N_BITS, Decoder::V2).unwrap();
+            computer.run(&instructions[..n]);
+            assert_eq!(computer.sum(), Some(expanded.values().sum::<u128>()));
+            for (&address, &value) in expanded.iter().take(100) {
+                assert_eq!(computer.get(address), Some(value));
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_many_floating_bits() {
+        let mut computer = DockingComputer::new(N_BITS, Decoder::V2).unwrap();
+        computer.run(
+            &parsers::input(N_BITS)(
+                "\
+mask = 00XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 1
+mask = X1XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 2
+mask = XXXX00000000000000000000000000000000
+mem[5] = 10",
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            computer.sum(),
+            Some((1 << 34) - 4 + 2 * ((1 << 35) - 8) + 10 * 16)
+        );
+        assert_eq!(computer.get(1 << 34), Some(2));
+        assert_eq!(computer.get((1 << 34) | 5), Some(10));
+        assert_eq!(computer.get(1 << 35), None);
+    }
+
+    #[test]
+    fn trace() {
+        let mut computer = DockingComputer::new(6, Decoder::V2).unwrap();
+        let trace = computer.run(
+            &parsers::input(6)(
+                "\
+mask = X1001X
+mem[42] = 50
+mask = 00X0XX
+mem[26] = 1",
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            trace.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "mask = X1001X",
+                "mem[X1101X] = 50 (mask X1001X, 4 addresses)",
+                "mask = 00X0XX",
+                "mem[01X0XX] = 1 (mask 00X0XX, 8 addresses)",
+            ]
+        );
+        assert_eq!(
+            computer.dump().into_iter().collect::<Vec<_>>(),
+            vec![
+                (16, 1),
+                (17, 1),
+                (18, 1),
+                (19, 1),
+                (24, 1),
+                (25, 1),
+                (26, 1),
+                (27, 1),
+                (58, 50),
+                (59, 50)
+            ]
+        );
+        assert!(parsers::input(6)("mask = 0000000").is_err());
+    }
+
+    #[test]
+    fn wide_computer() {
+        assert_eq!(DockingComputer::new(0, Decoder::V1), None);
+        assert_eq!(DockingComputer::new(129, Decoder::V1), None);
+        let mut computer = DockingComputer::new(MAX_BITS, Decoder::V1).unwrap();
+        let instructions = parsers::input(MAX_BITS)(&format!(
+            "mask = 1{}\nmem[{}] = 3",
+            "X".repeat(127),
+            u128::MAX
+        ))
+        .unwrap();
+        computer.run(&instructions);
+        assert_eq!(computer.get(u128::MAX), Some(3 | 1 << 127));
+
+        let mut computer = DockingComputer::new(100, Decoder::V2).unwrap();
+        computer.run(
+            &parsers::input(100)(&format!(
+                "mask = {}{}0\nmem[1] = 2",
+                "0".repeat(39),
+                "X".repeat(60)
+            ))
+            .unwrap(),
+        );
+        assert_eq!(computer.sum(), Some(2 << 60));
+    }
+
+    #[test]
+    fn all_floating_bits() {
+        let all_floating = format!("mask = {}", "X".repeat(128));
+        let mut computer = DockingComputer::new(MAX_BITS, Decoder::V2).unwrap();
+        let trace = computer
+            .run(&parsers::input(MAX_BITS)(&format!("{}\nmem[0] = 1", all_floating)).unwrap());
+        assert_eq!(
+            trace[1].to_string(),
+            format!(
+                "mem[{}] = 1 (mask {}, 2^128 addresses)",
+                "X".repeat(128),
+                "X".repeat(128)
+            )
+        );
+        assert_eq!(trace[1].written.unwrap().0.n_addresses(), None);
+        assert_eq!(computer.get(u128::MAX), Some(1));
+        assert_eq!(computer.sum(), None);
+
+        let mut computer = DockingComputer::new(MAX_BITS, Decoder::V2).unwrap();
+        computer.run(
+            &parsers::input(MAX_BITS)(&format!(
+                "{}\nmem[0] = 0\nmask = {}\nmem[5] = 7",
+                all_floating,
+                "0".repeat(128)
+            ))
+            .unwrap(),
+        );
+        assert_eq!(computer.sum(), Some(7));
+
+        let mut computer = DockingComputer::new(MAX_BITS, Decoder::V1).unwrap();
+        computer.run(
+            &parsers::input(MAX_BITS)(&format!(
+                "{}\nmem[0] = {}\nmem[1] = 1",
+                all_floating,
+                u128::MAX
+            ))
+            .unwrap(),
+        );
+        assert_eq!(computer.sum(), None);
+    }
+
+    #[test]
+    fn address_pattern_width() {
+        let mask = Mask { ones: 1, zeros: 2 };
+        assert_eq!(AddressPattern::new(5, &mask, 0), AddressPattern::single(0));
+        assert_eq!(AddressPattern::new(5, &mask, 1).n_addresses(), Some(1));
+        assert_eq!(
+            AddressPattern::new(5, &mask, MAX_BITS + 1),
+            AddressPattern::new(5, &mask, MAX_BITS)
+        );
+    }
 }
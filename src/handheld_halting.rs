@@ -1,5 +1,14 @@
 //! Day 8
 
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    ops::Range,
+    str::FromStr,
+};
+
+use nom::error::Error;
+
 trait Solution {
     fn part_1(&self) -> i32;
     fn part_2(&self) -> i32;
@@ -7,17 +16,16 @@ trait Solution {
 impl Solution for str {
     fn part_1(&self) -> i32 {
         let mut runner = Runner::new(parsers::input(self).expect("Failed to parse the input"));
-        find_loop(&mut runner);
-        if runner.is_finished() {
-            panic!("No loop found");
+        match runner.run(None) {
+            Halt::Loop(_) => runner.acc(),
+            halt => panic!("No loop found: {:?}", halt),
         }
-        runner.acc
     }
     fn part_2(&self) -> i32 {
         if let Some(runner) = fix_loop(Runner::new(
             parsers::input(self).expect("Failed to parse the input"),
         )) {
-            runner.acc
+            runner.acc()
         } else {
             panic!("Couldn't fix");
         }
@@ -27,6 +35,10 @@ impl Solution for str {
 pub type Instruction = (Op, i32);
 pub type BootCode = Vec<Instruction>;
 
+pub fn parse(s: &str) -> Result<BootCode, Error<&str>> {
+    parsers::input(s)
+}
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Op {
     Nop,
@@ -34,79 +46,701 @@ pub enum Op {
     Jmp,
 }
 
+// An instruction set with more registers and conditional jumps. `Base`
+// instructions operate on the first register.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ExtendedOp {
+    Base(Instruction),
+    Add(usize, i32),
+    Copy(usize, usize),
+    Jz(usize, i32),
+    Jnz(usize, i32),
+}
+
+pub trait Execute {
+    const N_REGISTERS: usize = 1;
+
+    // Updates the registers and returns the offset to the next instruction.
+    // Arithmetic wraps around on overflow.
+    fn execute(&self, registers: &mut [i32]) -> Result<isize, Halt>;
+    // Whether the jump offset depends on the registers. If no instruction is
+    // conditional, visiting an instruction twice means that the program loops.
+    fn is_conditional(&self) -> bool {
+        false
+    }
+}
+impl Execute for Instruction {
+    fn execute(&self, registers: &mut [i32]) -> Result<isize, Halt> {
+        Ok(match *self {
+            (Op::Acc, value) => {
+                let acc = register(registers, 0)?;
+                *acc = acc.wrapping_add(value);
+                1
+            }
+            (Op::Jmp, offset) => offset as isize,
+            (Op::Nop, _) => 1,
+        })
+    }
+}
+impl Execute for ExtendedOp {
+    const N_REGISTERS: usize = 4;
+
+    fn execute(&self, registers: &mut [i32]) -> Result<isize, Halt> {
+        Ok(match *self {
+            ExtendedOp::Base(instruction) => instruction.execute(registers)?,
+            ExtendedOp::Add(r, value) => {
+                let r = register(registers, r)?;
+                *r = r.wrapping_add(value);
+                1
+            }
+            ExtendedOp::Copy(dst, src) => {
+                let value = *register(registers, src)?;
+                *register(registers, dst)? = value;
+                1
+            }
+            ExtendedOp::Jz(r, offset) if *register(registers, r)? == 0 => offset as isize,
+            ExtendedOp::Jnz(r, offset) if *register(registers, r)? != 0 => offset as isize,
+            ExtendedOp::Jz(..) | ExtendedOp::Jnz(..) => 1,
+        })
+    }
+    fn is_conditional(&self) -> bool {
+        matches!(self, ExtendedOp::Jz(..) | ExtendedOp::Jnz(..))
+    }
+}
+
+fn register(registers: &mut [i32], r: usize) -> Result<&mut i32, Halt> {
+    registers.get_mut(r).ok_or(Halt::InvalidRegister(r))
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Halt {
+    // The instruction right after the last one was reached.
+    Terminated,
+    OutOfBounds(isize),
+    StepLimit,
+    Loop(usize),
+    // The current instruction uses a register that doesn't exist.
+    InvalidRegister(usize),
+}
+
 #[derive(Debug, Clone)]
-pub struct Runner {
-    pub code: BootCode,
-    pub ip: usize,
-    pub acc: i32,
+pub struct Runner<I = Instruction> {
+    pub code: Vec<I>,
+    pub ip: isize,
+    pub registers: Vec<i32>,
 }
-impl Runner {
-    pub fn new(code: BootCode) -> Self {
+impl<I: Execute> Runner<I> {
+    pub fn new(code: Vec<I>) -> Self {
         Self {
             code,
             ip: 0,
-            acc: 0,
+            registers: vec![0; I::N_REGISTERS],
+        }
+    }
+    pub fn acc(&self) -> i32 {
+        self.registers[0]
+    }
+    pub fn status(&self) -> Option<Halt> {
+        let len = self.code.len() as isize;
+        if self.ip == len {
+            Some(Halt::Terminated)
+        } else if self.ip < 0 || self.ip > len {
+            Some(Halt::OutOfBounds(self.ip))
+        } else {
+            None
         }
     }
     pub fn is_finished(&self) -> bool {
-        self.ip >= self.code.len()
+        self.status() == Some(Halt::Terminated)
     }
-    pub fn execute_one(&mut self) -> &mut Self {
-        match self.code[self.ip] {
-            (Op::Acc, value) => {
-                self.acc += value;
-                self.ip += 1;
+    pub fn step(&mut self) -> Result<(), Halt> {
+        if let Some(halt) = self.status() {
+            return Err(halt);
+        }
+        self.ip += self.code[self.ip as usize].execute(&mut self.registers)?;
+        Ok(())
+    }
+    // Runs until the program halts, either on its own or because it would
+    // loop forever or exceed `step_limit`.
+    pub fn run(&mut self, step_limit: Option<usize>) -> Halt {
+        let conditional = self.code.iter().any(Execute::is_conditional);
+        let mut seen_ips = HashSet::new();
+        let mut seen_states = HashSet::new();
+        for steps in 0.. {
+            if let Some(halt) = self.status() {
+                return halt;
             }
-            (Op::Jmp, offset) => {
-                if offset >= 0 {
-                    self.ip += offset as usize;
-                } else {
-                    self.ip -= (-offset) as usize;
-                }
+            let is_new = if conditional {
+                seen_states.insert((self.ip, self.registers.clone()))
+            } else {
+                seen_ips.insert(self.ip)
+            };
+            if !is_new {
+                return Halt::Loop(self.ip as usize);
+            }
+            if step_limit.is_some_and(|limit| steps >= limit) {
+                return Halt::StepLimit;
+            }
+            if let Err(halt) = self.step() {
+                return halt;
             }
-            (Op::Nop, _) => self.ip += 1,
         }
-        self
+        unreachable!()
     }
 }
 
-fn find_loop(runner: &mut Runner) -> Vec<usize> {
+fn find_loop<I: Execute>(runner: &mut Runner<I>) -> Vec<usize> {
     let mut seen = Vec::new();
-    while !runner.is_finished() && !seen.contains(&runner.ip) {
-        seen.push(runner.ip);
-        runner.execute_one();
+    while runner.status().is_none() && !seen.contains(&(runner.ip as usize)) {
+        seen.push(runner.ip as usize);
+        if runner.step().is_err() {
+            break;
+        }
     }
     seen
 }
-fn fix_loop(runner: Runner) -> Option<Runner> {
-    for i in find_loop(&mut runner.clone())
+fn fix_loop(mut runner: Runner) -> Option<Runner> {
+    let ip = ControlFlowGraph::new(&runner.code)
+        .repairs()
         .into_iter()
-        .rev()
-        .filter(|i| matches!(runner.code[*i].0, Op::Jmp | Op::Nop))
-    {
-        let mut runner = runner.clone();
-        runner.code.get_mut(i).unwrap().0 = match runner.code[i].0 {
-            Op::Jmp => Op::Nop,
-            Op::Nop => Op::Jmp,
-            _ => panic!(),
+        .next()?;
+    let op = &mut runner.code[ip].0;
+    *op = flipped(*op)?;
+    if runner.run(None) == Halt::Terminated {
+        Some(runner)
+    } else {
+        None
+    }
+}
+fn flipped(op: Op) -> Option<Op> {
+    match op {
+        Op::Jmp => Some(Op::Nop),
+        Op::Nop => Some(Op::Jmp),
+        Op::Acc => None,
+    }
+}
+fn target(ip: usize, (op, value): Instruction) -> isize {
+    match op {
+        Op::Jmp => ip as isize + value as isize,
+        Op::Nop | Op::Acc => ip as isize + 1,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    code: BootCode,
+    targets: Vec<isize>,
+    // Indexed by target; the last entry holds the instructions that terminate.
+    predecessors: Vec<Vec<usize>>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(code: &[Instruction]) -> Self {
+        let targets: Vec<_> = code
+            .iter()
+            .enumerate()
+            .map(|(ip, &instruction)| target(ip, instruction))
+            .collect();
+        let mut predecessors = vec![Vec::new(); code.len() + 1];
+        for (ip, &target) in targets.iter().enumerate() {
+            if (0..=code.len() as isize).contains(&target) {
+                predecessors[target as usize].push(ip);
+            }
+        }
+        Self {
+            code: code.to_vec(),
+            targets,
+            predecessors,
+        }
+    }
+
+    // Instructions executed from the start until the program halts or an
+    // instruction is about to be executed a second time.
+    pub fn path(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.code.len()];
+        let mut path = Vec::new();
+        let mut ip = 0;
+        while (0..self.code.len() as isize).contains(&ip) && !visited[ip as usize] {
+            visited[ip as usize] = true;
+            path.push(ip as usize);
+            ip = self.targets[ip as usize];
+        }
+        path
+    }
+    // Whether each instruction eventually leads to the end of the program,
+    // found by walking the edges backwards from the end.
+    pub fn terminating(&self) -> Vec<bool> {
+        let end = self.code.len();
+        let mut terminating = vec![false; end + 1];
+        terminating[end] = true;
+        let mut to_visit = vec![end];
+        while let Some(next) = to_visit.pop() {
+            for &ip in &self.predecessors[next] {
+                if !terminating[ip] {
+                    terminating[ip] = true;
+                    to_visit.push(ip);
+                }
+            }
+        }
+        terminating
+    }
+    // Instructions on the executed path that make the program terminate when
+    // flipped between `jmp` and `nop`, in execution order. Empty if the
+    // program already terminates.
+    pub fn repairs(&self) -> Vec<usize> {
+        let terminating = self.terminating();
+        if terminating[0] {
+            return Vec::new();
+        }
+        // None of the instructions on the path terminate, so the new path
+        // cannot go through the flipped instruction again.
+        self.path()
+            .into_iter()
+            .filter(|&ip| {
+                let (op, value) = self.code[ip];
+                flipped(op).is_some_and(|op| {
+                    let target = target(ip, (op, value));
+                    (0..=self.code.len() as isize).contains(&target) && terminating[target as usize]
+                })
+            })
+            .collect()
+    }
+
+    // Instructions executed from the start, in address order.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.code.len()];
+        for ip in self.path() {
+            reachable[ip] = true;
+        }
+        reachable
+    }
+    // Jumps that land neither on an instruction nor right after the last one.
+    pub fn out_of_bounds(&self) -> Vec<usize> {
+        (0..self.code.len())
+            .filter(|&ip| !(0..=self.code.len() as isize).contains(&self.targets[ip]))
+            .collect()
+    }
+    // Addresses targeted by a `jmp`, including the end of the program.
+    pub fn jump_targets(&self) -> BTreeSet<usize> {
+        self.code
+            .iter()
+            .zip(&self.targets)
+            .filter(|((op, _), &target)| {
+                *op == Op::Jmp && (0..=self.code.len() as isize).contains(&target)
+            })
+            .map(|(_, &target)| target as usize)
+            .collect()
+    }
+    // Maximal runs of instructions that are only entered from the first one
+    // and only left from the last one.
+    pub fn blocks(&self) -> Vec<Range<usize>> {
+        let mut leaders: BTreeSet<_> = self.jump_targets();
+        leaders.insert(0);
+        for (ip, (op, _)) in self.code.iter().enumerate() {
+            if *op == Op::Jmp {
+                leaders.insert(ip + 1);
+            }
+        }
+        let leaders: Vec<_> = leaders
+            .into_iter()
+            .filter(|&ip| ip < self.code.len())
+            .chain(std::iter::once(self.code.len()))
+            .collect();
+        leaders.windows(2).map(|w| w[0]..w[1]).collect()
+    }
+    // Cycles of instructions, each in execution order. Every instruction has
+    // a single successor, so they are disjoint.
+    pub fn loops(&self) -> Vec<Vec<usize>> {
+        let mut done = vec![false; self.code.len()];
+        let mut loops = Vec::new();
+        for start in 0..self.code.len() {
+            let mut walk = Vec::new();
+            let mut ip = start as isize;
+            while (0..self.code.len() as isize).contains(&ip) && !done[ip as usize] {
+                done[ip as usize] = true;
+                walk.push(ip as usize);
+                ip = self.targets[ip as usize];
+            }
+            if let Some(pos) = walk.iter().position(|&i| i as isize == ip) {
+                loops.push(walk.split_off(pos));
+            }
+        }
+        loops
+    }
+}
+
+// An annotated, assembler-style view of a program. Jumps within bounds refer
+// to labels and every instruction is tagged with the block and loop it
+// belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    code: BootCode,
+    blocks: Vec<Range<usize>>,
+    labels: BTreeMap<usize, usize>,
+    reachable: Vec<bool>,
+    out_of_bounds: Vec<usize>,
+    loops: Vec<Vec<usize>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ListingError {
+    Parse(Error<String>),
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+}
+
+impl Listing {
+    pub fn new(code: &[Instruction]) -> Self {
+        let graph = ControlFlowGraph::new(code);
+        Self {
+            code: code.to_vec(),
+            blocks: graph.blocks(),
+            labels: graph
+                .jump_targets()
+                .into_iter()
+                .enumerate()
+                .map(|(label, address)| (address, label))
+                .collect(),
+            reachable: graph.reachable(),
+            out_of_bounds: graph.out_of_bounds(),
+            loops: graph.loops(),
+        }
+    }
+
+    pub fn code(&self) -> &[Instruction] {
+        &self.code
+    }
+    pub fn blocks(&self) -> &[Range<usize>] {
+        &self.blocks
+    }
+    pub fn block_of(&self, ip: usize) -> Option<usize> {
+        self.blocks.iter().position(|block| block.contains(&ip))
+    }
+    pub fn label(&self, address: usize) -> Option<String> {
+        self.labels.get(&address).map(|label| format!("L{}", label))
+    }
+    pub fn unreachable(&self) -> Vec<usize> {
+        (0..self.code.len())
+            .filter(|&ip| !self.reachable[ip])
+            .collect()
+    }
+    pub fn out_of_bounds(&self) -> &[usize] {
+        &self.out_of_bounds
+    }
+    pub fn loops(&self) -> &[Vec<usize>] {
+        &self.loops
+    }
+    pub fn loop_of(&self, ip: usize) -> Option<usize> {
+        self.loops.iter().position(|l| l.contains(&ip))
+    }
+
+    fn annotations(&self, ip: usize) -> Vec<String> {
+        let mut annotations = Vec::new();
+        if !self.reachable[ip] {
+            annotations.push("unreachable".to_owned());
+        }
+        if let Some(l) = self.loop_of(ip) {
+            annotations.push(format!("loop {}", l));
+        }
+        if self.out_of_bounds.contains(&ip) {
+            annotations.push("out of bounds".to_owned());
+        }
+        annotations
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "; block {}", i)?;
+            for ip in block.clone() {
+                if let Some(label) = self.label(ip) {
+                    writeln!(f, "{}:", label)?;
+                }
+                let (op, value) = self.code[ip];
+                let target = target(ip, (op, value));
+                let operand = match self.labels.get(&(target as usize)) {
+                    Some(label) if op == Op::Jmp && target >= 0 => format!("L{}", label),
+                    _ => format!("{:+}", value),
+                };
+                let instruction = format!("{} {}", op, operand);
+                let annotations = self.annotations(ip);
+                if annotations.is_empty() {
+                    writeln!(f, "    {}", instruction)?;
+                } else {
+                    writeln!(f, "    {:<12}; {}", instruction, annotations.join(", "))?;
+                }
+            }
+        }
+        if let Some(label) = self.label(self.code.len()) {
+            writeln!(f, "{}:", label)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Listing {
+    type Err = ListingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let statements = parsers::listing(s)
+            .map_err(|e| ListingError::Parse(Error::new(e.input.to_owned(), e.code)))?;
+        let mut addresses = HashMap::new();
+        let mut address = 0usize;
+        for statement in &statements {
+            match statement {
+                parsers::Statement::Label(label) => {
+                    if addresses.insert(*label, address).is_some() {
+                        return Err(ListingError::DuplicateLabel(label.to_string()));
+                    }
+                }
+                parsers::Statement::Instruction(..) => address += 1,
+            }
+        }
+        let mut code = Vec::new();
+        for statement in statements {
+            if let parsers::Statement::Instruction(op, operand) = statement {
+                let value = match operand {
+                    parsers::Operand::Offset(value) => value,
+                    parsers::Operand::Label(label) => match addresses.get(label) {
+                        Some(&address) => address as i32 - code.len() as i32,
+                        None => return Err(ListingError::UndefinedLabel(label.to_owned())),
+                    },
+                };
+                code.push((op, value));
+            }
+        }
+        Ok(Self::new(&code))
+    }
+}
+
+impl fmt::Display for ListingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListingError::Parse(e) => write!(f, "parse error: {}", e),
+            ListingError::UndefinedLabel(label) => write!(f, "undefined label: {}", label),
+            ListingError::DuplicateLabel(label) => write!(f, "duplicate label: {}", label),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Nop => "nop",
+            Op::Acc => "acc",
+            Op::Jmp => "jmp",
+        })
+    }
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Halt::Terminated => write!(f, "terminated"),
+            Halt::OutOfBounds(ip) => write!(f, "out of bounds at {}", ip),
+            Halt::StepLimit => write!(f, "step limit reached"),
+            Halt::Loop(ip) => write!(f, "loop at {}", ip),
+            Halt::InvalidRegister(r) => write!(f, "invalid register {}", r),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Breakpoint {
+    Address(usize),
+    Opcode(Op),
+}
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Watchpoint {
+    // The accumulator changes value.
+    Changed,
+    // The accumulator becomes equal to the given value.
+    Equals(i32),
+}
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Stop {
+    Halted(Halt),
+    Breakpoint(Breakpoint),
+    // The address given to `run_to` was reached.
+    ReachedTarget(usize),
+    Watchpoint {
+        watchpoint: Watchpoint,
+        old: i32,
+        new: i32,
+    },
+}
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Address(address) => write!(f, "{}", address),
+            Breakpoint::Opcode(op) => write!(f, "{}", op),
+        }
+    }
+}
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::Changed => write!(f, "any change"),
+            Watchpoint::Equals(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct TraceEntry {
+    pub ip: usize,
+    pub instruction: Instruction,
+    pub acc_delta: i32,
+}
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Snapshot {
+    ip: isize,
+    registers: Vec<i32>,
+    trace_len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    runner: Runner,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    trace: Vec<TraceEntry>,
+}
+
+impl Debugger {
+    pub fn new(runner: Runner) -> Self {
+        Self {
+            runner,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            trace: Vec::new(),
+        }
+    }
+    pub fn runner(&self) -> &Runner {
+        &self.runner
+    }
+    pub fn trace(&self) -> &[TraceEntry] {
+        &self.trace
+    }
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| *b != breakpoint);
+        self.breakpoints.len() != len
+    }
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    // Executes a single instruction, ignoring breakpoints and watchpoints.
+    pub fn step(&mut self) -> Result<TraceEntry, Halt> {
+        let ip = self.runner.ip;
+        let old = self.runner.acc();
+        self.runner.step()?;
+        let entry = TraceEntry {
+            ip: ip as usize,
+            instruction: self.runner.code[ip as usize],
+            acc_delta: self.runner.acc().wrapping_sub(old),
         };
-        find_loop(&mut runner);
-        if runner.is_finished() {
-            return Some(runner);
+        self.trace.push(entry);
+        Ok(entry)
+    }
+    // Runs until a breakpoint is hit before executing an instruction, a
+    // watchpoint triggers after executing one, or the program halts. The
+    // instruction at the current position is always executed.
+    pub fn cont(&mut self) -> Stop {
+        self.run_until(|_| false)
+    }
+    pub fn run_to(&mut self, address: usize) -> Stop {
+        self.run_until(|ip| ip == address)
+    }
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ip: self.runner.ip,
+            registers: self.runner.registers.clone(),
+            trace_len: self.trace.len(),
+        }
+    }
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.runner.ip = snapshot.ip;
+        self.runner.registers = snapshot.registers.clone();
+        self.trace.truncate(snapshot.trace_len);
+    }
+
+    fn run_until(&mut self, stop_at: impl Fn(usize) -> bool) -> Stop {
+        let mut seen = HashSet::new();
+        loop {
+            let ip = self.runner.ip;
+            if !seen.is_empty() {
+                if let Some(halt) = self.runner.status() {
+                    return Stop::Halted(halt);
+                }
+                let (op, _) = self.runner.code[ip as usize];
+                if let Some(&breakpoint) = self.breakpoints.iter().find(|b| match b {
+                    Breakpoint::Address(address) => *address == ip as usize,
+                    Breakpoint::Opcode(opcode) => *opcode == op,
+                }) {
+                    return Stop::Breakpoint(breakpoint);
+                }
+                if stop_at(ip as usize) {
+                    return Stop::ReachedTarget(ip as usize);
+                }
+                // Breakpoints inside a loop take precedence over detecting it.
+                if !seen.insert(ip) {
+                    return Stop::Halted(Halt::Loop(ip as usize));
+                }
+            } else {
+                seen.insert(ip);
+            }
+            let old = self.runner.acc();
+            if let Err(halt) = self.step() {
+                return Stop::Halted(halt);
+            }
+            let new = self.runner.acc();
+            if let Some(&watchpoint) = self.watchpoints.iter().find(|w| match w {
+                Watchpoint::Changed => old != new,
+                Watchpoint::Equals(value) => old != new && new == *value,
+            }) {
+                return Stop::Watchpoint {
+                    watchpoint,
+                    old,
+                    new,
+                };
+            }
         }
     }
-    None
 }
 
 mod parsers {
     use nom::{
         branch::alt,
         bytes::complete::tag,
-        character::complete::{char, line_ending},
-        combinator::value,
+        character::complete::{
+            alpha1, alphanumeric1, char, line_ending, not_line_ending, space0, space1,
+        },
+        combinator::{map, opt, recognize, value},
         error::Error,
-        multi::separated_list1,
-        sequence::separated_pair,
+        multi::{many0, separated_list0, separated_list1},
+        sequence::{delimited, pair, separated_pair, terminated},
         IResult,
     };
 
@@ -114,19 +748,62 @@ mod parsers {
 
     use super::{BootCode, Instruction, Op};
 
+    pub enum Statement<'a> {
+        Label(&'a str),
+        Instruction(Op, Operand<'a>),
+    }
+    pub enum Operand<'a> {
+        Offset(i32),
+        Label(&'a str),
+    }
+
     pub fn input(s: &str) -> Result<BootCode, Error<&str>> {
         finished_parser(separated_list1(line_ending, instruction))(s)
     }
+    pub fn listing(s: &str) -> Result<Vec<Statement<'_>>, Error<&str>> {
+        finished_parser(map(
+            separated_list0(
+                line_ending,
+                delimited(
+                    space0,
+                    pair(
+                        opt(terminated(label, pair(char(':'), space0))),
+                        opt(separated_pair(
+                            op,
+                            space1,
+                            alt((
+                                map(signed_integer, Operand::Offset),
+                                map(label, Operand::Label),
+                            )),
+                        )),
+                    ),
+                    pair(space0, opt(pair(char(';'), not_line_ending))),
+                ),
+            ),
+            |lines| {
+                lines
+                    .into_iter()
+                    .flat_map(|(label, instruction)| {
+                        label.map(Statement::Label).into_iter().chain(
+                            instruction.map(|(op, operand)| Statement::Instruction(op, operand)),
+                        )
+                    })
+                    .collect()
+            },
+        ))(s)
+    }
     fn instruction(s: &str) -> IResult<&str, Instruction> {
-        separated_pair(
-            alt((
-                value(Op::Nop, tag("nop")),
-                value(Op::Acc, tag("acc")),
-                value(Op::Jmp, tag("jmp")),
-            )),
-            char(' '),
-            signed_integer,
-        )(s)
+        separated_pair(op, char(' '), signed_integer)(s)
+    }
+    fn op(s: &str) -> IResult<&str, Op> {
+        alt((
+            value(Op::Nop, tag("nop")),
+            value(Op::Acc, tag("acc")),
+            value(Op::Jmp, tag("jmp")),
+        ))(s)
+    }
+    fn label(s: &str) -> IResult<&str, &str> {
+        recognize(pair(alpha1, many0(alt((alphanumeric1, tag("_"))))))(s)
     }
 }
 
@@ -178,7 +855,7 @@ acc +6"
         ]);
         assert_eq!(find_loop(&mut runner), vec![0, 1, 2, 6, 7, 3, 4]);
         assert_eq!(runner.ip, 1);
-        assert_eq!(runner.acc, 5);
+        assert_eq!(runner.acc(), 5);
     }
 
     #[test]
@@ -201,11 +878,329 @@ acc +6"
         ]);
         let runner = fix_loop(runner).unwrap();
         assert_eq!(runner.code[7].0, Op::Nop);
-        assert_eq!(runner.acc, 8);
+        assert_eq!(runner.acc(), 8);
     }
 
     #[test]
     fn part_2() {
         assert_eq!(include_str!("inputs/day_8").part_2(), 2001);
     }
+
+    #[test]
+    fn halt_states() {
+        let code = vec![(Op::Acc, 1), (Op::Jmp, 2), (Op::Acc, 2)];
+        let mut runner = Runner::new(code.clone());
+        assert_eq!(runner.run(None), Halt::Terminated);
+        assert_eq!(runner.acc(), 1);
+        assert_eq!(runner.step(), Err(Halt::Terminated));
+
+        let mut runner = Runner::new(code.clone());
+        assert_eq!(runner.run(Some(1)), Halt::StepLimit);
+        assert_eq!((runner.ip, runner.acc()), (1, 1));
+
+        let mut runner = Runner::new(vec![(Op::Nop, 0), (Op::Jmp, -2)]);
+        assert_eq!(runner.run(None), Halt::OutOfBounds(-1));
+
+        let mut runner = Runner::new(vec![(Op::Jmp, 3), (Op::Nop, 0)]);
+        assert_eq!(runner.run(None), Halt::OutOfBounds(3));
+        assert_eq!(runner.step(), Err(Halt::OutOfBounds(3)));
+
+        let mut runner = Runner::new(vec![(Op::Acc, 1), (Op::Jmp, 0)]);
+        assert_eq!(runner.run(None), Halt::Loop(1));
+        assert_eq!(runner.acc(), 1);
+    }
+
+    #[test]
+    fn extended_instruction_set() {
+        // Adds 2 to the accumulator 5 times.
+        let mut runner = Runner::new(vec![
+            ExtendedOp::Add(1, 5),
+            ExtendedOp::Base((Op::Acc, 2)),
+            ExtendedOp::Add(1, -1),
+            ExtendedOp::Jnz(1, -2),
+            ExtendedOp::Copy(2, 0),
+        ]);
+        assert_eq!(runner.run(None), Halt::Terminated);
+        assert_eq!(runner.registers, vec![10, 0, 10, 0]);
+
+        let mut runner = Runner::new(vec![ExtendedOp::Jz(3, 0)]);
+        assert_eq!(runner.run(None), Halt::Loop(0));
+
+        let mut runner = Runner::new(vec![ExtendedOp::Add(0, 1), ExtendedOp::Jnz(0, -1)]);
+        assert_eq!(runner.run(Some(100)), Halt::StepLimit);
+
+        let mut runner = Runner::new(vec![ExtendedOp::Add(1, 1), ExtendedOp::Copy(4, 1)]);
+        assert_eq!(runner.run(None), Halt::InvalidRegister(4));
+        assert_eq!((runner.ip, runner.registers[1]), (1, 1));
+        assert_eq!(runner.step(), Err(Halt::InvalidRegister(4)));
+        let mut runner = Runner::new(vec![ExtendedOp::Jz(usize::MAX, 1)]);
+        assert_eq!(runner.run(None), Halt::InvalidRegister(usize::MAX));
+
+        let mut runner = Runner::new(vec![(Op::Acc, i32::MAX), (Op::Acc, 2)]);
+        assert_eq!(runner.run(None), Halt::Terminated);
+        assert_eq!(runner.acc(), i32::MIN + 1);
+        let mut runner = Runner::new(vec![ExtendedOp::Add(3, i32::MIN), ExtendedOp::Add(3, -1)]);
+        assert_eq!(runner.run(None), Halt::Terminated);
+        assert_eq!(runner.registers[3], i32::MAX);
+    }
+
+    #[test]
+    fn repairs() {
+        let example = ControlFlowGraph::new(&[
+            (Op::Nop, 0),
+            (Op::Acc, 1),
+            (Op::Jmp, 4),
+            (Op::Acc, 3),
+            (Op::Jmp, -3),
+            (Op::Acc, -99),
+            (Op::Acc, 1),
+            (Op::Jmp, -4),
+            (Op::Acc, 6),
+        ]);
+        assert_eq!(example.path(), vec![0, 1, 2, 6, 7, 3, 4]);
+        assert_eq!(
+            example.terminating(),
+            vec![false, false, false, false, false, false, false, false, true, true]
+        );
+        assert_eq!(example.repairs(), vec![7]);
+
+        let ambiguous = ControlFlowGraph::new(&[(Op::Nop, 2), (Op::Jmp, -1), (Op::Nop, 0)]);
+        assert_eq!(ambiguous.repairs(), vec![0, 1]);
+
+        let terminating = ControlFlowGraph::new(&[(Op::Nop, 2), (Op::Acc, 1)]);
+        assert!(terminating.repairs().is_empty());
+
+        let out_of_bounds = ControlFlowGraph::new(&[(Op::Jmp, 5), (Op::Acc, 1)]);
+        assert_eq!(out_of_bounds.path(), vec![0]);
+        assert_eq!(out_of_bounds.repairs(), vec![0]);
+    }
+
+    #[test]
+    fn repairs_match_brute_force() {
+        let code = parsers::input(include_str!("inputs/day_8")).unwrap();
+        let brute_force: Vec<_> = (0..code.len())
+            .filter(|&ip| {
+                let mut runner = Runner::new(code.clone());
+                match flipped(code[ip].0) {
+                    Some(op) => runner.code[ip].0 = op,
+                    None => return false,
+                }
+                runner.run(None) == Halt::Terminated
+            })
+            .collect();
+        let mut repairs = ControlFlowGraph::new(&code).repairs();
+        repairs.sort_unstable();
+        assert_eq!(repairs, brute_force);
+    }
+
+    #[test]
+    fn listing() {
+        let code = parsers::input(
+            "\
+nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6",
+        )
+        .unwrap();
+        let listing = Listing::new(&code);
+        assert_eq!(listing.blocks(), &[0..1, 1..3, 3..5, 5..6, 6..8, 8..9]);
+        assert_eq!(listing.label(3), Some("L1".to_owned()));
+        assert_eq!(listing.label(2), None);
+        assert_eq!(listing.unreachable(), vec![5, 8]);
+        assert_eq!(listing.loops(), &[vec![1, 2, 6, 7, 3, 4]]);
+        assert_eq!(listing.loop_of(7), Some(0));
+        assert_eq!(listing.block_of(7), Some(4));
+        assert!(listing.out_of_bounds().is_empty());
+        assert_eq!(
+            listing.to_string(),
+            "\
+; block 0
+    nop +0
+; block 1
+L0:
+    acc +1      ; loop 0
+    jmp L2      ; loop 0
+; block 2
+L1:
+    acc +3      ; loop 0
+    jmp L0      ; loop 0
+; block 3
+    acc -99     ; unreachable
+; block 4
+L2:
+    acc +1      ; loop 0
+    jmp L1      ; loop 0
+; block 5
+    acc +6      ; unreachable
+"
+        );
+        assert_eq!(listing.to_string().parse(), Ok(listing));
+    }
+
+    #[test]
+    fn listing_round_trip() {
+        let code = parsers::input(include_str!("inputs/day_8")).unwrap();
+        let listing = Listing::new(&code);
+        assert_eq!(
+            listing.to_string().parse::<Listing>().unwrap().code(),
+            &code[..]
+        );
+        let fixed = Listing::new(&fix_loop(Runner::new(code)).unwrap().code);
+        assert!(fixed.loops().is_empty());
+        assert!(fixed.label(fixed.code().len()).is_some());
+    }
+
+    #[test]
+    fn parse_listing() {
+        let listing: Listing = "\
+start:  nop +0 ; comment
+        jmp end
+        jmp +1000
+        jmp start
+end:
+"
+        .parse()
+        .unwrap();
+        assert_eq!(
+            listing.code(),
+            &[(Op::Nop, 0), (Op::Jmp, 3), (Op::Jmp, 1000), (Op::Jmp, -3)]
+        );
+        assert_eq!(listing.out_of_bounds(), &[2]);
+        assert_eq!(listing.unreachable(), vec![2, 3]);
+        assert_eq!(listing.label(4), Some("L1".to_owned()));
+        assert_eq!(
+            "jmp nowhere".parse::<Listing>(),
+            Err(ListingError::UndefinedLabel("nowhere".to_owned()))
+        );
+        assert_eq!(
+            "a:\na:".parse::<Listing>(),
+            Err(ListingError::DuplicateLabel("a".to_owned()))
+        );
+    }
+
+    #[test]
+    fn debugger() {
+        let mut debugger = Debugger::new(Runner::new(vec![
+            (Op::Nop, 0),
+            (Op::Acc, 1),
+            (Op::Jmp, 4),
+            (Op::Acc, 3),
+            (Op::Jmp, -3),
+            (Op::Acc, -99),
+            (Op::Acc, 1),
+            (Op::Jmp, -4),
+            (Op::Acc, 6),
+        ]));
+        debugger.add_breakpoint(Breakpoint::Address(6));
+        assert_eq!(debugger.cont(), Stop::Breakpoint(Breakpoint::Address(6)));
+        assert_eq!(debugger.runner().acc(), 1);
+        let snapshot = debugger.snapshot();
+
+        assert!(debugger.remove_breakpoint(Breakpoint::Address(6)));
+        debugger.add_breakpoint(Breakpoint::Opcode(Op::Jmp));
+        assert_eq!(
+            debugger.cont(),
+            Stop::Breakpoint(Breakpoint::Opcode(Op::Jmp))
+        );
+        assert_eq!(debugger.runner().ip, 7);
+
+        debugger.add_watchpoint(Watchpoint::Equals(5));
+        assert_eq!(
+            debugger.cont(),
+            Stop::Watchpoint {
+                watchpoint: Watchpoint::Equals(5),
+                old: 2,
+                new: 5
+            }
+        );
+        assert!(debugger.remove_breakpoint(Breakpoint::Opcode(Op::Jmp)));
+        assert_eq!(debugger.cont(), Stop::Halted(Halt::Loop(4)));
+        assert_eq!(
+            debugger
+                .trace()
+                .iter()
+                .map(|t| (t.ip, t.acc_delta))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, 0),
+                (1, 1),
+                (2, 0),
+                (6, 1),
+                (7, 0),
+                (3, 3),
+                (4, 0),
+                (1, 1),
+                (2, 0),
+                (6, 1),
+                (7, 0),
+                (3, 3)
+            ]
+        );
+
+        debugger.restore(&snapshot);
+        assert_eq!(debugger.runner().ip, 6);
+        assert_eq!(debugger.runner().acc(), 1);
+        assert_eq!(debugger.trace().len(), 3);
+        assert_eq!(
+            debugger.step(),
+            Ok(TraceEntry {
+                ip: 6,
+                instruction: (Op::Acc, 1),
+                acc_delta: 1
+            })
+        );
+        assert!(debugger.remove_watchpoint(Watchpoint::Equals(5)));
+        assert_eq!(debugger.run_to(4), Stop::ReachedTarget(4));
+        debugger.add_breakpoint(Breakpoint::Opcode(Op::Jmp));
+        assert_eq!(
+            debugger.run_to(6),
+            Stop::Breakpoint(Breakpoint::Opcode(Op::Jmp))
+        );
+    }
+
+    #[test]
+    fn debugger_in_loop() {
+        let code = vec![(Op::Acc, 1), (Op::Jmp, -1)];
+        let mut debugger = Debugger::new(Runner::new(code.clone()));
+        assert_eq!(debugger.run_to(0), Stop::ReachedTarget(0));
+        assert_eq!(debugger.runner().acc(), 1);
+        assert_eq!(debugger.run_to(0), Stop::ReachedTarget(0));
+        assert_eq!(debugger.runner().acc(), 2);
+
+        let mut debugger = Debugger::new(Runner::new(code));
+        debugger.add_breakpoint(Breakpoint::Address(1));
+        assert_eq!(debugger.cont(), Stop::Breakpoint(Breakpoint::Address(1)));
+        assert_eq!(debugger.cont(), Stop::Breakpoint(Breakpoint::Address(1)));
+        assert_eq!(debugger.runner().acc(), 2);
+        assert!(debugger.remove_breakpoint(Breakpoint::Address(1)));
+        assert_eq!(debugger.cont(), Stop::Halted(Halt::Loop(1)));
+        assert_eq!(debugger.runner().acc(), 3);
+        assert_eq!(Halt::Loop(1).to_string(), "loop at 1");
+        assert_eq!(Breakpoint::Opcode(Op::Jmp).to_string(), "jmp");
+        assert_eq!(Watchpoint::Equals(-3).to_string(), "-3");
+    }
+
+    #[test]
+    fn debugger_halts() {
+        let mut debugger = Debugger::new(Runner::new(vec![(Op::Acc, 1), (Op::Acc, 2)]));
+        debugger.add_watchpoint(Watchpoint::Changed);
+        assert_eq!(
+            debugger.cont(),
+            Stop::Watchpoint {
+                watchpoint: Watchpoint::Changed,
+                old: 0,
+                new: 1
+            }
+        );
+        assert!(debugger.remove_watchpoint(Watchpoint::Changed));
+        assert_eq!(debugger.cont(), Stop::Halted(Halt::Terminated));
+        assert_eq!(debugger.step(), Err(Halt::Terminated));
+        assert_eq!(debugger.trace().len(), 2);
+    }
 }
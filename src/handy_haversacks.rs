@@ -1,27 +1,240 @@
 //! Day 7
 
 use std::{
-    collections::{HashMap, HashSet},
+    borrow::Borrow,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
     hash::Hash,
 };
 
+use itertools::Itertools;
+
+use nom::error::Error;
+
 trait Solution {
     fn part_1(&self) -> usize;
     fn part_2(&self) -> usize;
 }
 impl Solution for str {
     fn part_1(&self) -> usize {
-        bfs(
-            "shiny gold",
-            &build_containee_map(&parsers::input(self).expect("Failed to parse the input")),
-        )
-        .len()
+        BagGraph::parse(self)
+            .expect("Failed to parse the input")
+            .ancestors("shiny gold")
+            .len()
     }
     fn part_2(&self) -> usize {
-        count_nesting(
-            "shiny gold",
-            &build_container_map(&parsers::input(self).expect("Failed to parse the input")),
-        )
+        BagGraph::parse(self)
+            .expect("Failed to parse the input")
+            .count_contained("shiny gold")
+            .expect("Invalid rules")
+    }
+}
+
+pub type Rule<'a> = (&'a str, Vec<(&'a str, usize)>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BagGraph<'a> {
+    contents: HashMap<&'a str, HashMap<&'a str, usize>>,
+    children: HashMap<&'a str, Vec<&'a str>>,
+    parents: HashMap<&'a str, Vec<&'a str>>,
+    duplicates: Vec<&'a str>,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Validation<'a> {
+    // Each cycle starts and ends with the same color.
+    pub cycles: Vec<Vec<&'a str>>,
+    pub undefined: Vec<&'a str>,
+    pub duplicates: Vec<&'a str>,
+    // Colors that cannot be reached from any outermost bag, i.e. that are only
+    // contained in cycles.
+    pub unreachable: Vec<&'a str>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Contents,
+    Containers,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BagError {
+    Cycle(Vec<String>),
+    Undefined(String),
+}
+
+impl<'a> BagGraph<'a> {
+    // When a color is defined more than once, its last definition wins.
+    pub fn new(rules: &[Rule<'a>]) -> Self {
+        let mut defined = HashSet::new();
+        let duplicates = rules
+            .iter()
+            .map(|(container, _)| *container)
+            .filter(|container| !defined.insert(*container))
+            .collect();
+        let mut kept = HashSet::new();
+        let mut rules: Vec<_> = rules
+            .iter()
+            .rev()
+            .filter(|(container, _)| kept.insert(*container))
+            .cloned()
+            .collect();
+        rules.reverse();
+        let contents = build_container_map(&rules);
+        let children = contents
+            .iter()
+            .map(|(&container, containees)| (container, containees.keys().copied().collect()))
+            .collect();
+        Self {
+            contents,
+            children,
+            parents: build_containee_map(&rules),
+            duplicates,
+        }
+    }
+    pub fn parse(s: &'a str) -> Result<Self, Error<&'a str>> {
+        Ok(Self::new(&parsers::input(s)?))
+    }
+
+    pub fn colors(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.contents.keys().copied()
+    }
+    pub fn contents(&self, color: &str) -> Option<&HashMap<&'a str, usize>> {
+        self.contents.get(color)
+    }
+    // All bags that eventually contain `color`.
+    pub fn ancestors(&self, color: &str) -> HashSet<&'a str> {
+        bfs(color, &self.parents).into_iter().copied().collect()
+    }
+    // All bags that `color` eventually contains.
+    pub fn descendants(&self, color: &str) -> HashSet<&'a str> {
+        bfs(color, &self.children).into_iter().copied().collect()
+    }
+    pub fn count_contained(&self, color: &str) -> Result<usize, BagError> {
+        count_nesting(color, &self.contents)
+    }
+    // Bags that are not contained in any other bag.
+    pub fn outermost(&self) -> HashSet<&'a str> {
+        self.colors()
+            .filter(|color| !self.parents.contains_key(color))
+            .collect()
+    }
+    // Shortest chain of bags going from `outer` down to `inner`, both included.
+    pub fn path(&self, outer: &str, inner: &str) -> Option<Vec<&'a str>> {
+        let (&start, _) = self.children.get_key_value(outer)?;
+        let mut previous = HashMap::new();
+        let mut to_visit = VecDeque::from(vec![start]);
+        while let Some(next) = to_visit.pop_front() {
+            if next == inner {
+                let mut path = vec![next];
+                while let Some(&prev) = previous.get(path.last().unwrap()) {
+                    path.push(prev);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &child in self.children.get(next).into_iter().flatten() {
+                if child != start && !previous.contains_key(child) {
+                    previous.insert(child, next);
+                    to_visit.push_back(child);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn validate(&self) -> Validation<'a> {
+        let mut colors = self.colors().collect_vec();
+        colors.sort_unstable();
+        let mut cycles = Vec::new();
+        let mut done = HashSet::new();
+        for color in &colors {
+            if let Some(cycle) = find_cycle(color, &|c| self.children(c), &mut done) {
+                done.extend(cycle.iter().copied());
+                cycles.push(cycle.into_iter().copied().collect());
+            }
+        }
+        let mut reachable = HashSet::new();
+        for color in self.outermost() {
+            reachable.insert(color);
+            reachable.extend(self.descendants(color));
+        }
+        Validation {
+            cycles,
+            undefined: self
+                .parents
+                .keys()
+                .copied()
+                .filter(|color| !self.contents.contains_key(color))
+                .sorted()
+                .collect(),
+            duplicates: self.duplicates.iter().copied().unique().collect(),
+            unreachable: colors
+                .into_iter()
+                .filter(|color| !reachable.contains(color))
+                .collect(),
+        }
+    }
+
+    // Graphviz representation of all the rules.
+    pub fn to_dot(&self, highlighted: &[&str]) -> String {
+        let nodes = self.colors().chain(self.parents.keys().copied()).collect();
+        self.dot(&nodes, highlighted)
+    }
+    // Graphviz representation of the bags reachable from `color`.
+    pub fn subgraph_to_dot(&self, color: &str, direction: Direction) -> String {
+        let mut nodes = match direction {
+            Direction::Contents => self.descendants(color),
+            Direction::Containers => self.ancestors(color),
+        };
+        nodes.extend(
+            self.colors()
+                .chain(self.parents.keys().copied())
+                .find(|&c| c == color),
+        );
+        self.dot(&nodes, &[color])
+    }
+
+    fn dot(&self, nodes: &HashSet<&'a str>, highlighted: &[&str]) -> String {
+        let mut dot = String::from("digraph bags {\n");
+        for node in nodes.iter().sorted() {
+            if highlighted.contains(node) {
+                dot += &format!("    {:?} [style=filled, fillcolor=gold];\n", node);
+            } else {
+                dot += &format!("    {:?};\n", node);
+            }
+        }
+        for container in nodes.iter().sorted() {
+            for (containee, n) in self
+                .contents
+                .get(container)
+                .into_iter()
+                .flatten()
+                .filter(|(containee, _)| nodes.contains(*containee))
+                .sorted()
+            {
+                dot += &format!("    {:?} -> {:?} [label={}];\n", container, containee, n);
+            }
+        }
+        dot + "}\n"
+    }
+    fn children(&self, color: &&'a str) -> Vec<&&'a str> {
+        self.children.get(color).into_iter().flatten().collect()
+    }
+}
+
+impl Validation<'_> {
+    pub fn is_valid(&self) -> bool {
+        self.cycles.is_empty()
+            && self.undefined.is_empty()
+            && self.duplicates.is_empty()
+            && self.unreachable.is_empty()
+    }
+}
+
+impl fmt::Display for BagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BagError::Cycle(cycle) => write!(f, "cycle: {}", cycle.join(" -> ")),
+            BagError::Undefined(color) => write!(f, "undefined color: {}", color),
+        }
     }
 }
 
@@ -41,21 +254,24 @@ where
     }
     result
 }
-fn bfs<'a, T>(start: T, data: &'a HashMap<T, Vec<T>>) -> HashSet<&'a T>
+fn bfs<'a, T, Q>(start: &Q, data: &'a HashMap<T, Vec<T>>) -> HashSet<&'a T>
 where
-    T: Eq + Hash,
+    T: Eq + Hash + Borrow<Q>,
+    Q: Eq + Hash + ?Sized,
 {
     let mut all_children = HashSet::new();
-    let mut to_visit = vec![&start];
-    while let Some(next) = to_visit.pop() {
-        if let Some(children) = data.get(next) {
-            for child in children {
-                if !all_children.contains(child) {
-                    to_visit.push(child);
-                    all_children.insert(child);
-                }
+    let mut to_visit = Vec::new();
+    let mut children = data.get(start);
+    loop {
+        for child in children.into_iter().flatten() {
+            if all_children.insert(child) {
+                to_visit.push(child);
             }
         }
+        match to_visit.pop() {
+            Some(next) => children = data.get(next.borrow()),
+            None => break,
+        }
     }
     all_children
 }
@@ -69,31 +285,84 @@ where
         .map(|(container, containees)| (container.clone(), containees.iter().cloned().collect()))
         .collect()
 }
-fn count_nesting<T>(end: T, data: &HashMap<T, HashMap<T, usize>>) -> usize
+fn count_nesting<T, Q>(end: &Q, data: &HashMap<T, HashMap<T, usize>>) -> Result<usize, BagError>
 where
-    T: Eq + Hash,
+    T: Eq + Hash + Borrow<Q> + ToString,
+    Q: Eq + Hash + ToString + ?Sized,
 {
+    let (end, _) = data
+        .get_key_value(end)
+        .ok_or_else(|| BagError::Undefined(end.to_string()))?;
     let mut sums = HashMap::<&T, usize>::new();
-    let mut to_visit = vec![&end];
-    while let Some(next) = to_visit.last() {
-        let entries = data.get(next).unwrap();
+    // Bags whose contents are being counted. Finding one of them again among
+    // the contents of a bag above it in the stack means that there is a cycle.
+    let mut expanded = HashSet::new();
+    let mut to_visit = vec![end];
+    while let Some(&next) = to_visit.last() {
+        let entries = data
+            .get::<T>(next)
+            .ok_or_else(|| BagError::Undefined(next.to_string()))?;
         if entries.iter().all(|(e, _)| sums.contains_key(e)) {
             sums.insert(
                 next,
                 entries.iter().map(|(e, &mul)| mul * (1 + sums[e])).sum(),
             );
+            expanded.remove(next);
             to_visit.pop().unwrap();
+        } else if entries.keys().any(|e| expanded.contains(e)) {
+            let children = |color| {
+                data.get::<T>(color)
+                    .into_iter()
+                    .flat_map(HashMap::keys)
+                    .collect()
+            };
+            let cycle = find_cycle(next, &children, &mut HashSet::new()).unwrap();
+            return Err(BagError::Cycle(
+                cycle.into_iter().map(ToString::to_string).collect(),
+            ));
         } else {
-            to_visit.extend(entries.iter().filter_map(|(e, _)| {
-                if !sums.contains_key(e) {
-                    Some(e)
-                } else {
-                    None
-                }
-            }));
+            expanded.insert(next);
+            to_visit.extend(entries.keys().filter(|e| !sums.contains_key(e)));
         }
     }
-    sums[&end]
+    Ok(sums[end])
+}
+// Depth-first search for a cycle reachable from `start`, skipping the nodes in
+// `done`, which are known not to lead to new cycles.
+fn find_cycle<'a, T, F>(start: &'a T, children: &F, done: &mut HashSet<&'a T>) -> Option<Vec<&'a T>>
+where
+    T: Eq + Hash,
+    F: Fn(&'a T) -> Vec<&'a T>,
+{
+    fn visit<'a, T, F>(
+        node: &'a T,
+        children: &F,
+        path: &mut Vec<&'a T>,
+        done: &mut HashSet<&'a T>,
+    ) -> Option<Vec<&'a T>>
+    where
+        T: Eq + Hash,
+        F: Fn(&'a T) -> Vec<&'a T>,
+    {
+        if let Some(ix) = path.iter().position(|&n| n == node) {
+            let mut cycle = path[ix..].to_vec();
+            cycle.push(node);
+            return Some(cycle);
+        }
+        if done.contains(node) {
+            return None;
+        }
+        path.push(node);
+        for child in children(node) {
+            if let Some(cycle) = visit(child, children, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(node);
+        None
+    }
+    visit(start, children, &mut Vec::new(), done)
 }
 
 mod parsers {
@@ -110,10 +379,12 @@ mod parsers {
 
     use crate::parsers::{finished_parser, integer};
 
-    pub fn input(s: &str) -> Result<Vec<(&str, Vec<(&str, usize)>)>, Error<&str>> {
+    use super::Rule;
+
+    pub fn input(s: &str) -> Result<Vec<Rule<'_>>, Error<&str>> {
         finished_parser(separated_list1(line_ending, line))(s)
     }
-    fn line(s: &str) -> IResult<&str, (&str, Vec<(&str, usize)>)> {
+    fn line(s: &str) -> IResult<&str, Rule<'_>> {
         separated_pair(
             color,
             tag(" bags contain "),
@@ -226,7 +497,7 @@ dotted black bags contain no other bags."
                     ("dotted black", vec![]),
                 ])
             ),
-            32
+            Ok(32)
         );
     }
 
@@ -249,7 +520,7 @@ dark violet bags contain no other bags."
                     .unwrap()
                 )
             ),
-            126
+            Ok(126)
         );
     }
 
@@ -257,4 +528,199 @@ dark violet bags contain no other bags."
     fn part_2() {
         assert_eq!(include_str!("inputs/day_7").part_2(), 10219);
     }
+
+    #[test]
+    fn bag_graph() {
+        let graph = BagGraph::parse(
+            "\
+light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.",
+        )
+        .unwrap();
+        assert_eq!(
+            graph.ancestors("muted yellow"),
+            ["light red", "dark orange"].iter().copied().collect()
+        );
+        assert_eq!(
+            graph.descendants("shiny gold"),
+            ["dark olive", "vibrant plum", "faded blue", "dotted black"]
+                .iter()
+                .copied()
+                .collect()
+        );
+        assert!(graph.descendants("faded blue").is_empty());
+        assert_eq!(graph.count_contained("dark olive"), Ok(7));
+        assert_eq!(graph.count_contained("shiny gold"), Ok(32));
+        assert!(graph.validate().is_valid());
+        assert_eq!(
+            graph.outermost(),
+            ["light red", "dark orange"].iter().copied().collect()
+        );
+        assert_eq!(
+            graph.path("light red", "faded blue"),
+            Some(vec!["light red", "muted yellow", "faded blue"])
+        );
+        assert_eq!(
+            graph.path("dark orange", "dotted black").map(|p| p.len()),
+            Some(5)
+        );
+        assert_eq!(
+            graph.path("shiny gold", "shiny gold"),
+            Some(vec!["shiny gold"])
+        );
+        assert_eq!(graph.path("faded blue", "shiny gold"), None);
+        assert_eq!(graph.path("pale green", "shiny gold"), None);
+    }
+
+    #[test]
+    fn validation() {
+        let graph = BagGraph::parse(
+            "\
+light red bags contain 1 dull black bag.
+bright white bags contain 2 shiny gold bags, 1 pale blue bag.
+shiny gold bags contain 1 bright white bag, 3 dull black bags.
+dull black bags contain no other bags.
+muted yellow bags contain 1 faded blue bag.
+faded blue bags contain 1 muted yellow bag.
+light red bags contain 1 bright white bag.",
+        )
+        .unwrap();
+        assert_eq!(
+            graph.validate(),
+            Validation {
+                cycles: vec![
+                    vec!["bright white", "shiny gold", "bright white"],
+                    vec!["faded blue", "muted yellow", "faded blue"],
+                ],
+                undefined: vec!["pale blue"],
+                duplicates: vec!["light red"],
+                unreachable: vec!["faded blue", "muted yellow"],
+            }
+        );
+        assert_eq!(graph.count_contained("dull black"), Ok(0));
+        assert!(matches!(
+            graph.count_contained("shiny gold"),
+            Err(BagError::Cycle(_))
+        ));
+        assert_eq!(
+            graph.count_contained("muted yellow"),
+            Err(BagError::Cycle(vec![
+                "faded blue".to_owned(),
+                "muted yellow".to_owned(),
+                "faded blue".to_owned()
+            ]))
+        );
+        assert_eq!(
+            graph.count_contained("pale green"),
+            Err(BagError::Undefined("pale green".to_owned()))
+        );
+    }
+
+    #[test]
+    fn duplicate_definition() {
+        let graph = BagGraph::parse(
+            "\
+light red bags contain 1 dull black bag.
+bright white bags contain no other bags.
+dull black bags contain no other bags.
+light red bags contain 2 bright white bags.",
+        )
+        .unwrap();
+        assert_eq!(graph.validate().duplicates, vec!["light red"]);
+        assert_eq!(
+            graph.descendants("light red"),
+            vec!["bright white"].into_iter().collect()
+        );
+        assert!(graph.ancestors("dull black").is_empty());
+        assert_eq!(
+            graph.ancestors("bright white"),
+            vec!["light red"].into_iter().collect()
+        );
+        assert_eq!(graph.path("light red", "dull black"), None);
+        assert_eq!(
+            graph.outermost(),
+            vec!["light red", "dull black"].into_iter().collect()
+        );
+        assert_eq!(graph.count_contained("light red"), Ok(2));
+    }
+
+    #[test]
+    fn undefined_contents() {
+        let graph = BagGraph::parse("light red bags contain 1 bright white bag.").unwrap();
+        assert_eq!(
+            graph.count_contained("light red"),
+            Err(BagError::Undefined("bright white".to_owned()))
+        );
+        assert_eq!(
+            BagError::Undefined("bright white".to_owned()).to_string(),
+            "undefined color: bright white"
+        );
+    }
+
+    #[test]
+    fn dot() {
+        let graph = BagGraph::parse(
+            "\
+light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag.
+faded blue bags contain no other bags.",
+        )
+        .unwrap();
+        assert_eq!(
+            graph.to_dot(&["shiny gold"]),
+            "\
+digraph bags {
+    \"bright white\";
+    \"dark olive\";
+    \"faded blue\";
+    \"light red\";
+    \"muted yellow\";
+    \"shiny gold\" [style=filled, fillcolor=gold];
+    \"bright white\" -> \"shiny gold\" [label=1];
+    \"light red\" -> \"bright white\" [label=1];
+    \"light red\" -> \"muted yellow\" [label=2];
+    \"muted yellow\" -> \"faded blue\" [label=9];
+    \"muted yellow\" -> \"shiny gold\" [label=2];
+    \"shiny gold\" -> \"dark olive\" [label=1];
+}
+"
+        );
+        assert_eq!(
+            graph.subgraph_to_dot("muted yellow", Direction::Contents),
+            "\
+digraph bags {
+    \"dark olive\";
+    \"faded blue\";
+    \"muted yellow\" [style=filled, fillcolor=gold];
+    \"shiny gold\";
+    \"muted yellow\" -> \"faded blue\" [label=9];
+    \"muted yellow\" -> \"shiny gold\" [label=2];
+    \"shiny gold\" -> \"dark olive\" [label=1];
+}
+"
+        );
+        assert_eq!(
+            graph.subgraph_to_dot("bright white", Direction::Containers),
+            "\
+digraph bags {
+    \"bright white\" [style=filled, fillcolor=gold];
+    \"light red\";
+    \"light red\" -> \"bright white\" [label=1];
+}
+"
+        );
+        assert_eq!(
+            graph.subgraph_to_dot("pale green", Direction::Contents),
+            "digraph bags {\n}\n"
+        );
+    }
 }
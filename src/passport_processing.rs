@@ -1,8 +1,18 @@
 //! Day 4
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::TryFrom,
+    fmt,
+    iter::FromIterator,
+    ops::RangeInclusive,
+    str::FromStr,
+};
 
-use nom::combinator::all_consuming;
+use itertools::Itertools;
+use nom::error::Error;
+
+use crate::parsers::finished_parser;
 
 trait Solution {
     fn part_1(&self) -> usize;
@@ -10,122 +20,685 @@ trait Solution {
 }
 impl Solution for str {
     fn part_1(&self) -> usize {
+        let schema = Schema::default();
         parsers::input(self)
             .expect("Failed to parse the input")
             .into_iter()
-            .filter(|passport| is_roughly_valid(passport))
+            .filter(|passport| is_roughly_valid(&schema, passport))
             .count()
     }
     fn part_2(&self) -> usize {
+        let schema = Schema::default();
         parsers::input(self)
             .expect("Failed to parse the input")
             .into_iter()
-            .map(|passport| passport.into_iter().collect())
-            .filter(|passport| is_valid(passport))
+            .filter(|passport| is_valid(&schema, &passport.iter().copied().collect()))
             .count()
     }
 }
 
-fn is_roughly_valid(passport: &[(&str, &str)]) -> bool {
-    let fields: HashSet<_> = passport.iter().map(|(f, _)| f).collect();
-    ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"]
-        .iter()
-        .all(|f| fields.contains(f))
-}
-
-fn is_valid(passport: &HashMap<&str, &str>) -> bool {
-    passport.get("byr").filter(|v| byr_valid(v)).is_some()
-        && passport.get("iyr").filter(|v| iyr_valid(v)).is_some()
-        && passport.get("eyr").filter(|v| eyr_valid(v)).is_some()
-        && passport.get("hgt").filter(|v| hgt_valid(v)).is_some()
-        && passport.get("hcl").filter(|v| hcl_valid(v)).is_some()
-        && passport.get("ecl").filter(|v| ecl_valid(v)).is_some()
-        && passport.get("pid").filter(|v| pid_valid(v)).is_some()
-}
-
-fn byr_valid(v: &str) -> bool {
-    v.parse::<u32>()
-        .ok()
-        .filter(|&v| v >= 1920 && v <= 2002)
-        .is_some()
-}
-fn iyr_valid(v: &str) -> bool {
-    v.parse::<u32>()
-        .ok()
-        .filter(|&v| v >= 2010 && v <= 2020)
-        .is_some()
-}
-fn eyr_valid(v: &str) -> bool {
-    v.parse::<u32>()
-        .ok()
-        .filter(|&v| v >= 2020 && v <= 2030)
-        .is_some()
-}
-fn hgt_valid(v: &str) -> bool {
-    parsers::height(v)
-        .ok()
-        .filter(|(s, h)| {
-            s == &""
-                && match h {
-                    Height::Cm(h) => *h >= 150 && *h <= 193,
-                    Height::In(h) => *h >= 59 && *h <= 76,
+const DEFAULT_SCHEMA: &str = "\
+byr: required range 1920-2002
+iyr: required range 2010-2020
+eyr: required range 2020-2030
+hgt: required units 150-193cm 59-76in
+hcl: required pattern #[0-9a-fA-F]{6}
+ecl: required enum amb blu brn gry grn hzl oth
+pid: required pattern [0-9]{9}
+cid: optional";
+
+fn is_roughly_valid(schema: &Schema, passport: &[(&str, &str)]) -> bool {
+    let fields: HashSet<_> = passport.iter().map(|(f, _)| *f).collect();
+    schema.required().all(|f| fields.contains(f.name()))
+}
+
+fn is_valid(schema: &Schema, passport: &HashMap<&str, &str>) -> bool {
+    schema.validate(passport).is_valid()
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Schema {
+    fields: Vec<FieldSpec>,
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FieldSpec {
+    name: String,
+    required: bool,
+    constraint: Constraint,
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Constraint {
+    Any,
+    Range(RangeInclusive<u32>),
+    Units(Vec<(String, RangeInclusive<u32>)>),
+    Pattern(Pattern),
+    Enumeration(Vec<String>),
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Pattern(Vec<Repetition>);
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Repetition {
+    class: CharClass,
+    min: usize,
+    max: usize,
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum CharClass {
+    Literal(char),
+    Set(Vec<RangeInclusive<char>>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum Reason {
+    Missing,
+    Malformed,
+    OutOfRange,
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Violation<'a> {
+    pub field: &'a FieldSpec,
+    pub value: Option<&'a str>,
+    pub reason: Reason,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Report<'a> {
+    violations: Vec<Violation<'a>>,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Summary {
+    pub passports: usize,
+    pub valid: usize,
+    pub by_reason: BTreeMap<Reason, usize>,
+    pub by_field: BTreeMap<(String, Reason), usize>,
+}
+
+impl Schema {
+    pub fn fields(&self) -> &[FieldSpec] {
+        &self.fields
+    }
+    pub fn field(&self, name: &str) -> Option<&FieldSpec> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+    pub fn required(&self) -> impl Iterator<Item = &FieldSpec> {
+        self.fields.iter().filter(|f| f.required)
+    }
+    pub fn validate<'a>(&'a self, passport: &HashMap<&str, &'a str>) -> Report<'a> {
+        Report {
+            violations: self
+                .fields
+                .iter()
+                .filter_map(|field| match passport.get(field.name()) {
+                    Some(v) => field.constraint.check(v).err().map(|reason| Violation {
+                        field,
+                        value: Some(v),
+                        reason,
+                    }),
+                    None if field.required => Some(Violation {
+                        field,
+                        value: None,
+                        reason: Reason::Missing,
+                    }),
+                    None => None,
+                })
+                .collect(),
+        }
+    }
+}
+impl Default for Schema {
+    fn default() -> Self {
+        DEFAULT_SCHEMA.parse().expect("Invalid default schema")
+    }
+}
+impl FromStr for Schema {
+    type Err = Error<String>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parsers::schema(s).map_err(owned_error)
+    }
+}
+
+impl FieldSpec {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+    pub fn constraint(&self) -> &Constraint {
+        &self.constraint
+    }
+}
+
+impl Constraint {
+    pub fn accepts(&self, v: &str) -> bool {
+        self.check(v).is_ok()
+    }
+    pub fn check(&self, v: &str) -> Result<(), Reason> {
+        fn in_range(range: &RangeInclusive<u32>, v: u32) -> Result<(), Reason> {
+            if range.contains(&v) {
+                Ok(())
+            } else {
+                Err(Reason::OutOfRange)
+            }
+        }
+        match self {
+            Constraint::Any => Ok(()),
+            Constraint::Range(range) => in_range(range, v.parse().map_err(|_| Reason::Malformed)?),
+            Constraint::Units(units) => {
+                let split = v.find(|c: char| !c.is_ascii_digit()).unwrap_or(v.len());
+                let (amount, unit) = v.split_at(split);
+                let amount = amount.parse().map_err(|_| Reason::Malformed)?;
+                let (_, range) = units
+                    .iter()
+                    .find(|(u, _)| u == unit)
+                    .ok_or(Reason::Malformed)?;
+                in_range(range, amount)
+            }
+            Constraint::Pattern(pattern) if pattern.matches(v) => Ok(()),
+            Constraint::Pattern(_) => Err(Reason::Malformed),
+            Constraint::Enumeration(values) if values.iter().any(|value| value == v) => Ok(()),
+            Constraint::Enumeration(_) => Err(Reason::OutOfRange),
+        }
+    }
+}
+
+impl Pattern {
+    // Tracks every position the repetitions matched so far can end at, so
+    // overlapping repetitions don't make the search exponential.
+    pub fn matches(&self, s: &str) -> bool {
+        let s = s.chars().collect_vec();
+        let mut reachable = vec![false; s.len() + 1];
+        reachable[0] = true;
+        for repetition in &self.0 {
+            let mut next = vec![false; s.len() + 1];
+            for start in (0..=s.len()).filter(|&ix| reachable[ix]) {
+                let longest = s[start..]
+                    .iter()
+                    .take(repetition.max)
+                    .take_while(|&&c| repetition.class.contains(c))
+                    .count();
+                for n in repetition.min..=longest {
+                    next[start + n] = true;
                 }
-        })
-        .is_some()
+            }
+            reachable = next;
+        }
+        reachable[s.len()]
+    }
+}
+
+impl CharClass {
+    fn contains(&self, c: char) -> bool {
+        match self {
+            CharClass::Literal(l) => *l == c,
+            CharClass::Set(ranges) => ranges.iter().any(|r| r.contains(&c)),
+        }
+    }
+}
+
+impl<'a> Report<'a> {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+    pub fn violations(&self) -> &[Violation<'a>] {
+        &self.violations
+    }
+}
+
+impl<'a> FromIterator<Report<'a>> for Summary {
+    fn from_iter<I: IntoIterator<Item = Report<'a>>>(iter: I) -> Self {
+        let mut summary = Summary::default();
+        for report in iter {
+            summary.passports += 1;
+            if report.is_valid() {
+                summary.valid += 1;
+            }
+            for violation in report.violations {
+                *summary.by_reason.entry(violation.reason).or_default() += 1;
+                *summary
+                    .by_field
+                    .entry((violation.field.name.clone(), violation.reason))
+                    .or_default() += 1;
+            }
+        }
+        summary
+    }
 }
-fn hcl_valid(v: &str) -> bool {
-    all_consuming(parsers::hex_color)(v).is_ok()
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (ix, field) in self.fields.iter().enumerate() {
+            if ix > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", field)?;
+        }
+        Ok(())
+    }
 }
-fn ecl_valid(v: &str) -> bool {
-    matches!(v, "amb" | "blu" | "brn" | "gry" | "grn" | "hzl" | "oth")
+impl fmt::Display for FieldSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            self.name,
+            if self.required {
+                "required"
+            } else {
+                "optional"
+            }
+        )?;
+        match self.constraint {
+            Constraint::Any => Ok(()),
+            ref constraint => write!(f, " {}", constraint),
+        }
+    }
 }
-fn pid_valid(v: &str) -> bool {
-    v.len() == 9 && v.chars().all(|c| c.is_digit(10))
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Any => write!(f, "any"),
+            Constraint::Range(range) => write!(f, "range {}-{}", range.start(), range.end()),
+            Constraint::Units(units) => write!(
+                f,
+                "units {}",
+                units
+                    .iter()
+                    .map(|(unit, range)| format!("{}-{}{}", range.start(), range.end(), unit))
+                    .join(" ")
+            ),
+            Constraint::Pattern(pattern) => write!(f, "pattern {}", pattern),
+            Constraint::Enumeration(values) => write!(f, "enum {}", values.join(" ")),
+        }
+    }
+}
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn escaped(c: char, special: &str) -> String {
+            if special.contains(c) {
+                format!("\\{}", c)
+            } else {
+                c.to_string()
+            }
+        }
+        for repetition in &self.0 {
+            match &repetition.class {
+                CharClass::Literal(c) => write!(f, "{}", escaped(*c, "[]{}?*+\\"))?,
+                CharClass::Set(ranges) => {
+                    write!(f, "[")?;
+                    for range in ranges {
+                        if range.start() == range.end() {
+                            write!(f, "{}", escaped(*range.start(), "]\\-"))?;
+                        } else {
+                            write!(f, "{}-{}", range.start(), range.end())?;
+                        }
+                    }
+                    write!(f, "]")?;
+                }
+            }
+            match (repetition.min, repetition.max) {
+                (1, 1) => Ok(()),
+                (0, 1) => write!(f, "?"),
+                (0, usize::MAX) => write!(f, "*"),
+                (1, usize::MAX) => write!(f, "+"),
+                (min, max) if min == max => write!(f, "{{{}}}", min),
+                (min, max) => write!(f, "{{{},{}}}", min, max),
+            }?;
+        }
+        Ok(())
+    }
+}
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Missing => write!(f, "missing"),
+            Reason::Malformed => write!(f, "malformed"),
+            Reason::OutOfRange => write!(f, "out of range"),
+        }
+    }
+}
+impl fmt::Display for Violation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field.name, self.reason)?;
+        if let Some(value) = self.value {
+            write!(f, " {:?}", value)?;
+        }
+        write!(f, " ({})", self.field.constraint)
+    }
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Passport {
+    pub birth_year: u32,
+    pub issue_year: u32,
+    pub expiration_year: u32,
+    pub height: Height,
+    pub hair_color: Color,
+    pub eye_color: EyeColor,
+    pub passport_id: PassportId,
+    pub other: BTreeMap<String, String>,
+}
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Height {
     Cm(u32),
     In(u32),
 }
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum EyeColor {
+    Amber,
+    Blue,
+    Brown,
+    Gray,
+    Green,
+    Hazel,
+    Other,
+}
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct PassportId(pub u32);
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PassportError {
+    pub field: String,
+    pub reason: Reason,
+}
+
+impl Passport {
+    // Checks the fields against the schema before converting them, so the
+    // types only have to parse values the schema already accepted.
+    pub fn from_fields(schema: &Schema, fields: &[(&str, &str)]) -> Result<Self, PassportError> {
+        fn typed_field<T: FromStr>(
+            fields: &mut HashMap<&str, &str>,
+            field: &str,
+        ) -> Result<T, PassportError> {
+            let error = |reason| PassportError {
+                field: field.to_owned(),
+                reason,
+            };
+            fields
+                .remove(field)
+                .ok_or_else(|| error(Reason::Missing))?
+                .parse()
+                .map_err(|_| error(Reason::Malformed))
+        }
+        let mut map: HashMap<_, _> = fields.iter().copied().collect();
+        if let Some(violation) = schema.validate(&map).violations().first() {
+            return Err(PassportError {
+                field: violation.field.name().to_owned(),
+                reason: violation.reason,
+            });
+        }
+        Ok(Passport {
+            birth_year: typed_field(&mut map, "byr")?,
+            issue_year: typed_field(&mut map, "iyr")?,
+            expiration_year: typed_field(&mut map, "eyr")?,
+            height: typed_field(&mut map, "hgt")?,
+            hair_color: typed_field(&mut map, "hcl")?,
+            eye_color: typed_field(&mut map, "ecl")?,
+            passport_id: typed_field(&mut map, "pid")?,
+            other: map
+                .into_iter()
+                .map(|(f, v)| (f.to_owned(), v.to_owned()))
+                .collect(),
+        })
+    }
+}
+
+impl TryFrom<&[(&str, &str)]> for Passport {
+    type Error = PassportError;
+
+    fn try_from(fields: &[(&str, &str)]) -> Result<Self, Self::Error> {
+        Passport::from_fields(&Schema::default(), fields)
+    }
+}
+
+impl FromStr for Height {
+    type Err = Error<String>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        finished_parser(parsers::height)(s).map_err(owned_error)
+    }
+}
+impl FromStr for Color {
+    type Err = Error<String>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        finished_parser(parsers::hex_color)(s).map_err(owned_error)
+    }
+}
+impl FromStr for EyeColor {
+    type Err = Error<String>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        finished_parser(parsers::eye_color)(s).map_err(owned_error)
+    }
+}
+impl FromStr for PassportId {
+    type Err = Error<String>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        finished_parser(parsers::passport_id)(s).map_err(owned_error)
+    }
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Height::Cm(h) => write!(f, "{}cm", h),
+            Height::In(h) => write!(f, "{}in", h),
+        }
+    }
+}
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+impl fmt::Display for EyeColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EyeColor::Amber => "amb",
+            EyeColor::Blue => "blu",
+            EyeColor::Brown => "brn",
+            EyeColor::Gray => "gry",
+            EyeColor::Green => "grn",
+            EyeColor::Hazel => "hzl",
+            EyeColor::Other => "oth",
+        })
+    }
+}
+impl fmt::Display for PassportId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:09}", self.0)
+    }
+}
+impl fmt::Display for PassportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+fn owned_error(e: Error<&str>) -> Error<String> {
+    Error::new(e.input.to_owned(), e.code)
+}
 
 mod parsers {
+    use std::ops::RangeInclusive;
+
     use nom::{
         branch::alt,
         bytes::complete::{is_not, tag, take_while_m_n},
-        character::complete::{alpha1, char, digit1, one_of},
-        combinator::{map_res, value},
+        character::complete::{
+            alpha1, alphanumeric1, anychar, char, line_ending, multispace0, none_of, one_of, space1,
+        },
+        combinator::{map, map_res, opt, value},
         error::Error,
-        multi::separated_list1,
-        sequence::separated_pair,
+        multi::{many1, separated_list1},
+        sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
         IResult,
     };
 
-    use crate::parsers::{double_line_ending, finished_parser};
+    use crate::parsers::{double_line_ending, finished_parser, integer};
+
+    use super::{
+        CharClass, Color, Constraint, EyeColor, FieldSpec, Height, PassportId, Pattern, Repetition,
+        Schema,
+    };
 
-    use super::Height;
+    pub type Fields<'a> = Vec<(&'a str, &'a str)>;
 
-    pub fn input(s: &str) -> Result<Vec<Vec<(&str, &str)>>, Error<&str>> {
+    pub fn input(s: &str) -> Result<Vec<Fields<'_>>, Error<&str>> {
         finished_parser(separated_list1(double_line_ending, passport))(s)
     }
-    pub fn passport(s: &str) -> IResult<&str, Vec<(&str, &str)>> {
+    pub fn passport(s: &str) -> IResult<&str, Fields<'_>> {
         separated_list1(
             one_of(" \n"),
             separated_pair(alpha1, char(':'), is_not(" \n")),
         )(s)
     }
 
-    pub fn hex_color(s: &str) -> IResult<&str, &str> {
-        let (s, _) = tag("#")(s)?;
-        take_while_m_n(6, 6, |c: char| c.is_digit(16))(s)
-    }
     pub fn height(s: &str) -> IResult<&str, Height> {
-        let (s, h) = map_res(digit1, |s: &str| s.parse())(s)?;
+        let (s, h) = integer(s)?;
         alt((
             value(Height::Cm(h), tag("cm")),
             value(Height::In(h), tag("in")),
         ))(s)
     }
+    pub fn hex_color(s: &str) -> IResult<&str, Color> {
+        let (s, _) = char('#')(s)?;
+        let (s, r) = hex_byte(s)?;
+        let (s, g) = hex_byte(s)?;
+        let (s, b) = hex_byte(s)?;
+        Ok((s, Color { r, g, b }))
+    }
+    fn hex_byte(s: &str) -> IResult<&str, u8> {
+        map_res(
+            take_while_m_n(2, 2, |c: char| c.is_ascii_hexdigit()),
+            |s: &str| u8::from_str_radix(s, 16),
+        )(s)
+    }
+    pub fn eye_color(s: &str) -> IResult<&str, EyeColor> {
+        alt((
+            value(EyeColor::Amber, tag("amb")),
+            value(EyeColor::Blue, tag("blu")),
+            value(EyeColor::Brown, tag("brn")),
+            value(EyeColor::Gray, tag("gry")),
+            value(EyeColor::Green, tag("grn")),
+            value(EyeColor::Hazel, tag("hzl")),
+            value(EyeColor::Other, tag("oth")),
+        ))(s)
+    }
+    pub fn passport_id(s: &str) -> IResult<&str, PassportId> {
+        map_res(
+            take_while_m_n(9, 9, |c: char| c.is_ascii_digit()),
+            |s: &str| s.parse().map(PassportId),
+        )(s)
+    }
+
+    pub fn schema(s: &str) -> Result<Schema, Error<&str>> {
+        finished_parser(map(
+            delimited(
+                multispace0,
+                separated_list1(many1(line_ending), field_spec),
+                multispace0,
+            ),
+            |fields| Schema { fields },
+        ))(s)
+    }
+    fn field_spec(s: &str) -> IResult<&str, FieldSpec> {
+        let (s, name) = terminated(alphanumeric1, tag(": "))(s)?;
+        let (s, required) = alt((value(true, tag("required")), value(false, tag("optional"))))(s)?;
+        let (s, constraint) = opt(preceded(space1, constraint))(s)?;
+        Ok((
+            s,
+            FieldSpec {
+                name: name.to_owned(),
+                required,
+                constraint: constraint.unwrap_or(Constraint::Any),
+            },
+        ))
+    }
+    fn constraint(s: &str) -> IResult<&str, Constraint> {
+        alt((
+            value(Constraint::Any, tag("any")),
+            map(preceded(tag("range "), range), Constraint::Range),
+            map(
+                preceded(
+                    tag("units "),
+                    separated_list1(space1, pair(range, map(alpha1, str::to_owned))),
+                ),
+                |units| {
+                    Constraint::Units(
+                        units
+                            .into_iter()
+                            .map(|(range, unit)| (unit, range))
+                            .collect(),
+                    )
+                },
+            ),
+            map(preceded(tag("pattern "), pattern), Constraint::Pattern),
+            map(
+                preceded(
+                    tag("enum "),
+                    separated_list1(space1, map(is_not(" \t\r\n"), str::to_owned)),
+                ),
+                Constraint::Enumeration,
+            ),
+        ))(s)
+    }
+    fn range(s: &str) -> IResult<&str, RangeInclusive<u32>> {
+        map(separated_pair(integer, char('-'), integer), |(min, max)| {
+            min..=max
+        })(s)
+    }
+
+    pub fn pattern(s: &str) -> IResult<&str, Pattern> {
+        map(many1(repetition), Pattern)(s)
+    }
+    fn repetition(s: &str) -> IResult<&str, Repetition> {
+        let (s, class) = char_class(s)?;
+        let (s, (min, max)) = map(
+            opt(alt((
+                delimited(
+                    char('{'),
+                    separated_pair(integer, char(','), integer),
+                    char('}'),
+                ),
+                map(delimited(char('{'), integer, char('}')), |n| (n, n)),
+                value((0, 1), char('?')),
+                value((0, usize::MAX), char('*')),
+                value((1, usize::MAX), char('+')),
+            ))),
+            |bounds| bounds.unwrap_or((1, 1)),
+        )(s)?;
+        Ok((s, Repetition { class, min, max }))
+    }
+    fn char_class(s: &str) -> IResult<&str, CharClass> {
+        alt((
+            map(
+                delimited(
+                    char('['),
+                    many1(alt((
+                        map(
+                            tuple((none_of("]\\"), char('-'), none_of("]\\"))),
+                            |(a, _, b)| a..=b,
+                        ),
+                        map(alt((preceded(char('\\'), anychar), none_of("]"))), |c| {
+                            c..=c
+                        }),
+                    ))),
+                    char(']'),
+                ),
+                CharClass::Set,
+            ),
+            map(preceded(char('\\'), anychar), CharClass::Literal),
+            map(none_of("[]{}?*+\\\r\n"), CharClass::Literal),
+        ))(s)
+    }
 }
 
 #[cfg(test)]
@@ -216,9 +789,302 @@ iyr:2011 ecl:brn hgt:59in"
         );
     }
 
+    #[test]
+    fn schema() {
+        assert_eq!(
+            "\
+name: required pattern [A-Z][a-z]+
+age: optional range 0-150
+size: required units 1-10m 100-1000cm
+color: optional enum red green
+note: optional
+"
+            .parse(),
+            Ok(Schema {
+                fields: vec![
+                    FieldSpec {
+                        name: "name".to_owned(),
+                        required: true,
+                        constraint: Constraint::Pattern(Pattern(vec![
+                            Repetition {
+                                class: CharClass::Set(vec!['A'..='Z']),
+                                min: 1,
+                                max: 1
+                            },
+                            Repetition {
+                                class: CharClass::Set(vec!['a'..='z']),
+                                min: 1,
+                                max: usize::MAX
+                            },
+                        ]))
+                    },
+                    FieldSpec {
+                        name: "age".to_owned(),
+                        required: false,
+                        constraint: Constraint::Range(0..=150)
+                    },
+                    FieldSpec {
+                        name: "size".to_owned(),
+                        required: true,
+                        constraint: Constraint::Units(vec![
+                            ("m".to_owned(), 1..=10),
+                            ("cm".to_owned(), 100..=1000)
+                        ])
+                    },
+                    FieldSpec {
+                        name: "color".to_owned(),
+                        required: false,
+                        constraint: Constraint::Enumeration(vec![
+                            "red".to_owned(),
+                            "green".to_owned()
+                        ])
+                    },
+                    FieldSpec {
+                        name: "note".to_owned(),
+                        required: false,
+                        constraint: Constraint::Any
+                    },
+                ]
+            })
+        );
+        assert!("name: mandatory".parse::<Schema>().is_err());
+    }
+
+    #[test]
+    fn pattern() {
+        let pattern = |s| parsers::pattern(s).unwrap().1;
+        assert!(pattern("#[0-9a-f]{6}").matches("#123abc"));
+        assert!(!pattern("#[0-9a-f]{6}").matches("#123abcd"));
+        assert!(pattern("a{1,3}ab").matches("aaab"));
+        assert!(!pattern("a{1,3}ab").matches("ab"));
+        assert!(pattern("x?y*z+").matches("zz"));
+        assert!(pattern("x?y*z+").matches("xyyz"));
+        assert!(!pattern("x?y*z+").matches("xxz"));
+        assert!(pattern("\\[[\\]-]\\]").matches("[-]"));
+        let backtracking = pattern("a*a*a*a*a*a*a*b");
+        assert!(!backtracking.matches(&"a".repeat(30)));
+        assert!(backtracking.matches(&format!("{}b", "a".repeat(30))));
+        assert!(pattern("a{0,2}a{2}").matches("aaaa"));
+        assert!(!pattern("a{0,2}a{2}").matches("aaaaa"));
+    }
+
+    #[test]
+    fn schema_round_trip() {
+        assert_eq!(Schema::default().to_string(), DEFAULT_SCHEMA);
+        let pattern = "\\[[\\]\\-]\\]a{2,3}b?c*d+";
+        assert_eq!(parsers::pattern(pattern).unwrap().1.to_string(), pattern);
+        let dash = Pattern(vec![Repetition {
+            class: CharClass::Set(vec!['a'..='a', '-'..='-', 'z'..='z']),
+            min: 1,
+            max: 1,
+        }]);
+        assert_eq!(dash.to_string(), "[a\\-z]");
+        assert_eq!(parsers::pattern(&dash.to_string()).unwrap().1, dash);
+
+        let schema: Schema = "\
+name: required pattern [A-Z][a-z]+
+age: optional range 0-150
+size: required units 1-10m 100-1000cm
+color: optional enum red green
+note: optional"
+            .parse()
+            .unwrap();
+        assert_eq!(schema.to_string().parse(), Ok(schema.clone()));
+        for field in schema.fields() {
+            let spec = format!("{}: required {}", field.name(), field.constraint());
+            assert_eq!(
+                spec.parse::<Schema>().unwrap().fields()[0].constraint(),
+                field.constraint()
+            );
+        }
+        assert_eq!(
+            "note: optional any".parse::<Schema>(),
+            "note: optional".parse()
+        );
+    }
+
+    #[test]
+    fn report() {
+        let schema = Schema::default();
+        let passport = parsers::passport("hgt:59cm ecl:zzz eyr:2038 hcl:74454a iyr:2023 byr:2007")
+            .unwrap()
+            .1
+            .into_iter()
+            .collect();
+        let report = schema.validate(&passport);
+        assert!(!report.is_valid());
+        assert_eq!(
+            report
+                .violations()
+                .iter()
+                .map(ToString::to_string)
+                .collect_vec(),
+            vec![
+                "byr: out of range \"2007\" (range 1920-2002)",
+                "iyr: out of range \"2023\" (range 2010-2020)",
+                "eyr: out of range \"2038\" (range 2020-2030)",
+                "hgt: out of range \"59cm\" (units 150-193cm 59-76in)",
+                "hcl: malformed \"74454a\" (pattern #[0-9a-fA-F]{6})",
+                "ecl: out of range \"zzz\" (enum amb blu brn gry grn hzl oth)",
+                "pid: missing (pattern [0-9]{9})",
+            ]
+        );
+        assert_eq!(
+            report.violations()[3],
+            Violation {
+                field: schema.field("hgt").unwrap(),
+                value: Some("59cm"),
+                reason: Reason::OutOfRange,
+            }
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let schema = Schema::default();
+        let summary: Summary = parsers::input(
+            "\
+eyr:1972 cid:100
+hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
+
+iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719
+
+hcl:dab227 iyr:2012
+ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277",
+        )
+        .unwrap()
+        .into_iter()
+        .map(|passport| passport.into_iter().collect::<HashMap<_, _>>())
+        .collect_vec()
+        .iter()
+        .map(|passport| schema.validate(passport))
+        .collect();
+        assert_eq!(summary.passports, 3);
+        assert_eq!(summary.valid, 1);
+        assert_eq!(
+            summary.by_reason,
+            vec![(Reason::Malformed, 3), (Reason::OutOfRange, 1)]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(
+            summary.by_field,
+            vec![
+                (("eyr".to_owned(), Reason::OutOfRange), 1),
+                (("hgt".to_owned(), Reason::Malformed), 1),
+                (("hcl".to_owned(), Reason::Malformed), 1),
+                (("pid".to_owned(), Reason::Malformed), 1),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn typed_passport() {
+        let fields = parsers::passport(
+            "\
+ecl:gry pid:060033327 eyr:2020 hcl:#fffffd
+byr:1937 iyr:2017 cid:147 hgt:183cm",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(
+            Passport::try_from(fields.as_slice()),
+            Ok(Passport {
+                birth_year: 1937,
+                issue_year: 2017,
+                expiration_year: 2020,
+                height: Height::Cm(183),
+                hair_color: Color {
+                    r: 0xff,
+                    g: 0xff,
+                    b: 0xfd
+                },
+                eye_color: EyeColor::Gray,
+                passport_id: PassportId(60_033_327),
+                other: vec![("cid".to_owned(), "147".to_owned())]
+                    .into_iter()
+                    .collect(),
+            })
+        );
+        assert_eq!(PassportId(60_033_327).to_string(), "060033327");
+        assert_eq!(
+            Passport::try_from(&[("byr", "1937"), ("iyr", "2017"), ("eyr", "2040")][..]),
+            Err(PassportError {
+                field: "eyr".to_owned(),
+                reason: Reason::OutOfRange
+            })
+        );
+        assert_eq!(
+            Passport::try_from(&[("byr", "1937"), ("iyr", "2017")][..]),
+            Err(PassportError {
+                field: "eyr".to_owned(),
+                reason: Reason::Missing
+            })
+        );
+
+        let schema: Schema = DEFAULT_SCHEMA
+            .replace("1920-2002", "1900-2002")
+            .parse()
+            .unwrap();
+        let old = [
+            ("byr", "1910"),
+            ("iyr", "2017"),
+            ("eyr", "2020"),
+            ("hgt", "183cm"),
+            ("hcl", "#FFFFFD"),
+            ("ecl", "gry"),
+            ("pid", "060033327"),
+        ];
+        assert_eq!(
+            Passport::try_from(&old[..]),
+            Err(PassportError {
+                field: "byr".to_owned(),
+                reason: Reason::OutOfRange
+            })
+        );
+        let passport = Passport::from_fields(&schema, &old).unwrap();
+        assert_eq!(passport.birth_year, 1910);
+        assert_eq!(passport.hair_color.to_string(), "#fffffd");
+        assert!(passport.other.is_empty());
+    }
+
+    #[test]
+    fn typed_fields() {
+        assert_eq!("60in".parse(), Ok(Height::In(60)));
+        assert!("190".parse::<Height>().is_err());
+        assert_eq!(
+            "#123abc".parse(),
+            Ok(Color {
+                r: 0x12,
+                g: 0x3a,
+                b: 0xbc
+            })
+        );
+        assert!("#123abz".parse::<Color>().is_err());
+        assert!("123abc".parse::<Color>().is_err());
+        assert_eq!("brn".parse(), Ok(EyeColor::Brown));
+        assert!("wat".parse::<EyeColor>().is_err());
+        assert_eq!("000000001".parse(), Ok(PassportId(1)));
+        assert!("0123456789".parse::<PassportId>().is_err());
+    }
+
+    #[test]
+    fn typed_matches_schema() {
+        let schema = Schema::default();
+        for passport in parsers::input(include_str!("inputs/day_4")).unwrap() {
+            assert_eq!(
+                Passport::try_from(passport.as_slice()).is_ok(),
+                is_valid(&schema, &passport.into_iter().collect())
+            );
+        }
+    }
+
     #[test]
     fn example_1() {
         assert!(is_roughly_valid(
+            &Schema::default(),
             &parsers::passport(
                 "\
 ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
@@ -228,6 +1094,7 @@ byr:1937 iyr:2017 cid:147 hgt:183cm"
             .1
         ));
         assert!(!is_roughly_valid(
+            &Schema::default(),
             &parsers::passport(
                 "\
 iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884
@@ -237,6 +1104,7 @@ hcl:#cfa07d byr:1929"
             .1
         ));
         assert!(is_roughly_valid(
+            &Schema::default(),
             &parsers::passport(
                 "\
 hcl:#ae17e1 iyr:2013
@@ -248,6 +1116,7 @@ hgt:179cm"
             .1
         ));
         assert!(!is_roughly_valid(
+            &Schema::default(),
             &parsers::passport(
                 "\
 hcl:#cfa07d eyr:2025 pid:166559648
@@ -265,19 +1134,22 @@ iyr:2011 ecl:brn hgt:59in"
 
     #[test]
     fn example_2() {
-        assert!(byr_valid("2002"));
-        assert!(!byr_valid("2003"));
-        assert!(hgt_valid("60in"));
-        assert!(hgt_valid("190cm"));
-        assert!(!hgt_valid("190in"));
-        assert!(!hgt_valid("190"));
-        assert!(hcl_valid("#123abc"));
-        assert!(!hcl_valid("#123abz"));
-        assert!(!hcl_valid("123abc"));
-        assert!(ecl_valid("brn"));
-        assert!(!ecl_valid("wat"));
-        assert!(pid_valid("000000001"));
-        assert!(!pid_valid("0123456789"));
+        let schema = Schema::default();
+        let valid = |field, v| schema.field(field).unwrap().constraint().accepts(v);
+        assert!(valid("byr", "2002"));
+        assert!(!valid("byr", "2003"));
+        assert!(valid("hgt", "60in"));
+        assert!(valid("hgt", "190cm"));
+        assert!(!valid("hgt", "190in"));
+        assert!(!valid("hgt", "190"));
+        assert!(valid("hcl", "#123abc"));
+        assert!(valid("hcl", "#123ABC"));
+        assert!(!valid("hcl", "#123abz"));
+        assert!(!valid("hcl", "123abc"));
+        assert!(valid("ecl", "brn"));
+        assert!(!valid("ecl", "wat"));
+        assert!(valid("pid", "000000001"));
+        assert!(!valid("pid", "0123456789"));
     }
 
     #[test]
@@ -301,7 +1173,7 @@ pid:3556412378 byr:2007"
         .unwrap()
         .into_iter()
         .map(|passport| passport.into_iter().collect::<HashMap<_, _>>())
-        .all(|passport| !is_valid(&passport)));
+        .all(|passport| !is_valid(&Schema::default(), &passport)));
     }
 
     #[test]
@@ -324,7 +1196,7 @@ iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719"
         .unwrap()
         .into_iter()
         .map(|passport| passport.into_iter().collect::<HashMap<_, _>>())
-        .all(|passport| is_valid(&passport)));
+        .all(|passport| is_valid(&Schema::default(), &passport)));
     }
 
     #[test]
@@ -9,9 +9,10 @@ impl Solution for str {
         parsers::input(self)
             .expect("Failed to parse the input")
             .into_iter()
-            .fold(HeadedShip::new(), |ship, (direction, amount)| {
+            .try_fold(HeadedShip::new(), |ship, (direction, amount)| {
                 ship.do_move(direction, amount)
             })
+            .expect("Failed to turn the ship")
             .position
             .get_manhattan_distance()
     }
@@ -19,25 +20,37 @@ impl Solution for str {
         parsers::input(self)
             .expect("Failed to parse the input")
             .into_iter()
-            .fold(WaypontedShip::new(), |ship, (direction, amount)| {
+            .try_fold(WaypontedShip::new(), |ship, (direction, amount)| {
                 ship.do_move(direction, amount)
             })
+            .expect("Failed to turn the ship")
             .position
             .get_manhattan_distance()
     }
 }
 
-struct HeadedShip {
-    heading: Cardinal,
-    position: Position,
+// Ships are generic over their coordinates: `Position` only supports
+// rotations by multiples of 90°, `Vector` supports any angle. Moves that
+// need an unsupported rotation fail with `None`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HeadedShip<P = Position> {
+    pub heading: P,
+    pub position: P,
 }
-struct WaypontedShip {
-    waypoint: Position,
-    position: Position,
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WaypontedShip<P = Position> {
+    pub waypoint: P,
+    pub position: P,
 }
-struct Position {
-    east: i32,
-    north: i32,
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Position {
+    pub east: i32,
+    pub north: i32,
+}
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Vector {
+    pub east: f64,
+    pub north: f64,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -61,96 +74,447 @@ pub enum Relative {
     Left,
 }
 
-trait Ship {
+pub trait Ship: Sized {
     fn move_cardinal(self, direction: Cardinal, amount: i32) -> Self;
-    fn move_relative(self, direction: Relative, amount: i32) -> Self;
+    fn move_relative(self, direction: Relative, amount: i32) -> Option<Self>;
 }
-trait ShipExt: Ship + Sized {
-    fn do_move(self, direction: Direction, amount: i32) -> Self {
+pub trait ShipExt: Ship + Sized {
+    fn do_move(self, direction: Direction, amount: i32) -> Option<Self> {
         match direction {
-            Direction::Cardinal(direction) => self.move_cardinal(direction, amount),
+            Direction::Cardinal(direction) => Some(self.move_cardinal(direction, amount)),
             Direction::Relative(direction) => self.move_relative(direction, amount),
         }
     }
 }
 impl<T: Ship> ShipExt for T {}
 
+pub trait Coordinates: Copy {
+    fn new(east: i32, north: i32) -> Self;
+    fn move_cardinal(self, direction: Cardinal, amount: i32) -> Self;
+    // Moves `times` times by `offset`.
+    fn move_by(self, offset: Self, times: i32) -> Self;
+    // `None` if the rotation can't be represented.
+    fn turn(self, right: bool, degrees: i32) -> Option<Self>;
+    fn to_vector(self) -> Vector;
+}
+
+pub trait Located {
+    type Coordinates: Coordinates;
+
+    fn position(&self) -> Self::Coordinates;
+    // Relative to the ship, for ships that follow a waypoint.
+    fn waypoint(&self) -> Option<Self::Coordinates> {
+        None
+    }
+}
+
 impl HeadedShip {
     pub fn new() -> Self {
+        Self::default()
+    }
+}
+impl<P: Coordinates> Default for HeadedShip<P> {
+    fn default() -> Self {
         Self {
-            heading: Cardinal::East,
-            position: Position::default(),
+            heading: P::new(1, 0),
+            position: P::new(0, 0),
         }
     }
 }
-impl Ship for HeadedShip {
+impl<P: Coordinates> Ship for HeadedShip<P> {
     fn move_cardinal(self, direction: Cardinal, amount: i32) -> Self {
         Self {
             position: self.position.move_cardinal(direction, amount),
             ..self
         }
     }
-    fn move_relative(self, direction: Relative, amount: i32) -> Self {
-        match direction {
-            Relative::Forward => {
-                let direction = self.heading;
-                self.move_cardinal(direction, amount)
-            }
+    fn move_relative(self, direction: Relative, amount: i32) -> Option<Self> {
+        Some(match direction {
+            Relative::Forward => Self {
+                position: self.position.move_by(self.heading, amount),
+                ..self
+            },
             Relative::Right => Self {
-                heading: self.heading.rotate_degrees(true, amount),
+                heading: self.heading.turn(true, amount)?,
                 ..self
             },
             Relative::Left => Self {
-                heading: self.heading.rotate_degrees(false, amount),
+                heading: self.heading.turn(false, amount)?,
                 ..self
             },
-        }
+        })
+    }
+}
+
+impl<P: Coordinates> Located for HeadedShip<P> {
+    type Coordinates = P;
+
+    fn position(&self) -> P {
+        self.position
     }
 }
 
 impl WaypontedShip {
     pub fn new() -> Self {
+        Self::default()
+    }
+}
+impl<P: Coordinates> Default for WaypontedShip<P> {
+    fn default() -> Self {
         Self {
-            waypoint: Position { east: 10, north: 1 },
-            position: Position::default(),
+            waypoint: P::new(10, 1),
+            position: P::new(0, 0),
         }
     }
 }
-impl Ship for WaypontedShip {
+impl<P: Coordinates> Ship for WaypontedShip<P> {
     fn move_cardinal(self, direction: Cardinal, amount: i32) -> Self {
         Self {
             waypoint: self.waypoint.move_cardinal(direction, amount),
             ..self
         }
     }
-    fn move_relative(self, direction: Relative, amount: i32) -> Self {
-        match direction {
-            Relative::Forward => {
-                let mut position = self.position;
-                for _ in 0..amount {
-                    position = position
-                        .move_cardinal(Cardinal::East, self.waypoint.east)
-                        .move_cardinal(Cardinal::North, self.waypoint.north);
-                }
-                Self { position, ..self }
-            }
+    fn move_relative(self, direction: Relative, amount: i32) -> Option<Self> {
+        Some(match direction {
+            Relative::Forward => Self {
+                position: self.position.move_by(self.waypoint, amount),
+                ..self
+            },
             Relative::Right => Self {
-                waypoint: self.waypoint.rotate_degrees(true, amount),
+                waypoint: self.waypoint.turn(true, amount)?,
                 ..self
             },
             Relative::Left => Self {
-                waypoint: self.waypoint.rotate_degrees(false, amount),
+                waypoint: self.waypoint.turn(false, amount)?,
                 ..self
             },
+        })
+    }
+}
+
+impl<P: Coordinates> Located for WaypontedShip<P> {
+    type Coordinates = P;
+
+    fn position(&self) -> P {
+        self.position
+    }
+    fn waypoint(&self) -> Option<P> {
+        Some(self.waypoint)
+    }
+}
+
+// Every state a ship goes through while following instructions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Voyage<S> {
+    ships: Vec<S>,
+}
+
+impl<S: Ship + Located + Copy> Voyage<S> {
+    pub fn new(start: S) -> Self {
+        Self { ships: vec![start] }
+    }
+    pub fn sail(
+        start: S,
+        instructions: impl IntoIterator<Item = (Direction, i32)>,
+    ) -> Option<Self> {
+        let mut voyage = Self::new(start);
+        for (direction, amount) in instructions {
+            voyage.record(direction, amount)?;
+        }
+        Some(voyage)
+    }
+    // Leaves the voyage unchanged if the ship can't make the move.
+    pub fn record(&mut self, direction: Direction, amount: i32) -> Option<S> {
+        let ship = self.end().do_move(direction, amount)?;
+        self.ships.push(ship);
+        Some(ship)
+    }
+
+    pub fn ships(&self) -> &[S] {
+        &self.ships
+    }
+    pub fn start(&self) -> S {
+        self.ships[0]
+    }
+    pub fn end(&self) -> S {
+        *self.ships.last().unwrap()
+    }
+    pub fn positions(&self) -> impl Iterator<Item = S::Coordinates> + '_ {
+        self.ships.iter().map(Located::position)
+    }
+    // Absolute positions of the waypoint, for ships that follow one.
+    pub fn waypoints(&self) -> impl Iterator<Item = S::Coordinates> + '_ {
+        self.ships
+            .iter()
+            .filter_map(|ship| Some(ship.position().move_by(ship.waypoint()?, 1)))
+    }
+
+    // Lower left and upper right corners of the area covered by the ship.
+    pub fn bounding_box(&self) -> (Vector, Vector) {
+        bounding_box(self.positions().map(Coordinates::to_vector))
+    }
+    pub fn distance(&self) -> f64 {
+        self.positions()
+            .map(Coordinates::to_vector)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|w| (w[1].east - w[0].east).hypot(w[1].north - w[0].north))
+            .sum()
+    }
+    // Position with the greatest Manhattan distance from the start.
+    pub fn farthest(&self) -> S::Coordinates {
+        let start = self.start().position().to_vector();
+        let distance = |p: &S::Coordinates| {
+            let v = p.to_vector();
+            (v.east - start.east).abs() + (v.north - start.north).abs()
+        };
+        self.positions()
+            .max_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+            .unwrap()
+    }
+
+    pub fn to_svg(&self) -> String {
+        let track: Vec<_> = self.positions().map(Coordinates::to_vector).collect();
+        let waypoints: Vec<_> = self.waypoints().map(Coordinates::to_vector).collect();
+        let (min, max) = bounding_box(track.iter().chain(&waypoints).copied());
+        let (width, height) = (
+            (max.east - min.east).max(1.),
+            (max.north - min.north).max(1.),
+        );
+        let stroke = width.max(height) / 200.;
+        // SVG's y axis points south; `0. - north` avoids printing `-0`.
+        let circle = |v: &Vector, r: f64, color: &str| {
+            format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>\n",
+                v.east,
+                0. - v.north,
+                r,
+                color
+            )
+        };
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            min.east - stroke * 4.,
+            -max.north - stroke * 4.,
+            width + stroke * 8.,
+            height + stroke * 8.
+        );
+        for waypoint in &waypoints {
+            svg += &circle(waypoint, stroke, "steelblue");
+        }
+        svg += &format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"{}\"/>\n",
+            track
+                .iter()
+                .map(|v| format!("{},{}", v.east, 0. - v.north))
+                .collect::<Vec<_>>()
+                .join(" "),
+            stroke
+        );
+        svg += &circle(&track[0], stroke * 3., "green");
+        svg += &circle(track.last().unwrap(), stroke * 3., "red");
+        svg += "</svg>\n";
+        svg
+    }
+    // Track drawn with `#` on a grid of the given size, north up. The start
+    // is marked with `S`, the end with `E` and waypoints with `w`. Empty if
+    // the grid has no cells.
+    pub fn to_ascii(&self, width: usize, height: usize) -> String {
+        if width == 0 || height == 0 {
+            return String::new();
+        }
+        let track: Vec<_> = self.positions().map(Coordinates::to_vector).collect();
+        let waypoints: Vec<_> = self.waypoints().map(Coordinates::to_vector).collect();
+        let (min, max) = bounding_box(track.iter().chain(&waypoints).copied());
+        let scale = |v: f64, min: f64, max: f64, cells: usize| {
+            if max > min {
+                (v - min) / (max - min) * (cells - 1) as f64
+            } else {
+                0.
+            }
+        };
+        let cell = |v: Vector| {
+            (
+                scale(v.east, min.east, max.east, width),
+                scale(max.north - v.north, 0., max.north - min.north, height),
+            )
+        };
+        let mut grid = vec![vec![' '; width]; height];
+        let mut plot =
+            |(x, y): (f64, f64), c: char| grid[y.round() as usize][x.round() as usize] = c;
+        for w in track.windows(2) {
+            let ((x0, y0), (x1, y1)) = (cell(w[0]), cell(w[1]));
+            let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.);
+            for i in 0..=steps as usize {
+                let t = i as f64 / steps;
+                plot((x0 + (x1 - x0) * t, y0 + (y1 - y0) * t), '#');
+            }
+        }
+        for &waypoint in &waypoints {
+            plot(cell(waypoint), 'w');
         }
+        plot(cell(track[0]), 'S');
+        plot(cell(*track.last().unwrap()), 'E');
+        grid.into_iter()
+            .map(|row| row.into_iter().collect::<String>().trim_end().to_owned())
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 }
 
-impl Default for Position {
-    fn default() -> Self {
-        Self { east: 0, north: 0 }
+// Ships that can work out how to reach a position.
+pub trait Plan: Ship + Located<Coordinates = Position> + Copy {
+    // Instruction sequences that may take the ship to `target`.
+    fn candidates(&self, target: Position) -> Vec<Vec<(Direction, i32)>>;
+}
+
+// Shortest candidate that actually takes the ship to `target`.
+pub fn plan<S: Plan>(ship: S, target: Position) -> Option<Vec<(Direction, i32)>> {
+    ship.candidates(target)
+        .into_iter()
+        .filter(|instructions| {
+            instructions
+                .iter()
+                .try_fold(ship, |ship, &(direction, amount)| {
+                    ship.do_move(direction, amount)
+                })
+                .is_some_and(|ship| ship.position() == target)
+        })
+        .min_by_key(Vec::len)
+}
+
+impl Plan for HeadedShip {
+    fn candidates(&self, target: Position) -> Vec<Vec<(Direction, i32)>> {
+        let mut candidates = vec![cardinal_moves(self.position, target)];
+        if let Some(amount) = multiple_of(self.heading, offset(self.position, target)) {
+            candidates.push(vec![(Direction::Relative(Relative::Forward), amount)]);
+        }
+        candidates
+    }
+}
+impl Plan for WaypontedShip {
+    fn candidates(&self, target: Position) -> Vec<Vec<(Direction, i32)>> {
+        let delta = offset(self.position, target);
+        if delta == Position::default() {
+            return vec![Vec::new()];
+        }
+        let mut candidates = Vec::new();
+        let mut waypoint = self.waypoint;
+        for degrees in (0..360).step_by(90) {
+            if let Some(amount) = multiple_of(waypoint, delta) {
+                let mut instructions = Vec::new();
+                if degrees > 0 {
+                    instructions.push((Direction::Relative(Relative::Right), degrees));
+                }
+                instructions.push((Direction::Relative(Relative::Forward), amount));
+                candidates.push(instructions);
+            }
+            waypoint = waypoint.rotate_right();
+        }
+        let steps = gcd(delta.east.unsigned_abs(), delta.north.unsigned_abs()) as i32;
+        let waypoint = Position {
+            east: delta.east / steps,
+            north: delta.north / steps,
+        };
+        let mut instructions = cardinal_moves(self.waypoint, waypoint);
+        instructions.push((Direction::Relative(Relative::Forward), steps));
+        candidates.push(instructions);
+        candidates
+    }
+}
+
+// Formats instructions the way the puzzle input does.
+pub fn format_instructions(instructions: &[(Direction, i32)]) -> String {
+    instructions
+        .iter()
+        .map(|&(direction, amount)| {
+            let c = match direction {
+                Direction::Cardinal(Cardinal::North) => 'N',
+                Direction::Cardinal(Cardinal::South) => 'S',
+                Direction::Cardinal(Cardinal::East) => 'E',
+                Direction::Cardinal(Cardinal::West) => 'W',
+                Direction::Relative(Relative::Forward) => 'F',
+                Direction::Relative(Relative::Right) => 'R',
+                Direction::Relative(Relative::Left) => 'L',
+            };
+            format!("{}{}", c, amount)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn offset(from: Position, to: Position) -> Position {
+    Position {
+        east: to.east - from.east,
+        north: to.north - from.north,
+    }
+}
+fn cardinal_moves(from: Position, to: Position) -> Vec<(Direction, i32)> {
+    let delta = offset(from, to);
+    let east = if delta.east > 0 {
+        (Cardinal::East, delta.east)
+    } else {
+        (Cardinal::West, -delta.east)
+    };
+    let north = if delta.north > 0 {
+        (Cardinal::North, delta.north)
+    } else {
+        (Cardinal::South, -delta.north)
+    };
+    vec![east, north]
+        .into_iter()
+        .filter(|&(_, amount)| amount != 0)
+        .map(|(direction, amount)| (Direction::Cardinal(direction), amount))
+        .collect()
+}
+// The positive `n` such that `n * step == delta`, if any.
+fn multiple_of(step: Position, delta: Position) -> Option<i32> {
+    let ratio = |step: i32, delta: i32| match (step, delta) {
+        (0, 0) => Some(None),
+        (0, _) => None,
+        _ if delta % step == 0 && delta / step > 0 => Some(Some(delta / step)),
+        _ => None,
+    };
+    match (
+        ratio(step.east, delta.east)?,
+        ratio(step.north, delta.north)?,
+    ) {
+        (Some(a), Some(b)) if a == b => Some(a),
+        (Some(n), None) | (None, Some(n)) => Some(n),
+        _ => None,
+    }
+}
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
 }
+
+fn bounding_box(points: impl Iterator<Item = Vector>) -> (Vector, Vector) {
+    let min = Vector {
+        east: f64::INFINITY,
+        north: f64::INFINITY,
+    };
+    let max = Vector {
+        east: f64::NEG_INFINITY,
+        north: f64::NEG_INFINITY,
+    };
+    points.fold((min, max), |(min, max), v| {
+        (
+            Vector {
+                east: min.east.min(v.east),
+                north: min.north.min(v.north),
+            },
+            Vector {
+                east: max.east.max(v.east),
+                north: max.north.max(v.north),
+            },
+        )
+    })
+}
+
 impl Position {
     pub fn move_cardinal(self, direction: Cardinal, amount: i32) -> Self {
         match direction {
@@ -173,11 +537,86 @@ impl Position {
         }
     }
     pub fn get_manhattan_distance(&self) -> u32 {
-        self.east.abs() as u32 + self.north.abs() as u32
+        self.east.unsigned_abs() + self.north.unsigned_abs()
+    }
+}
+impl From<Cardinal> for Position {
+    fn from(direction: Cardinal) -> Self {
+        Self::default().move_cardinal(direction, 1)
+    }
+}
+impl Coordinates for Position {
+    fn new(east: i32, north: i32) -> Self {
+        Self { east, north }
+    }
+    fn to_vector(self) -> Vector {
+        self.into()
+    }
+    fn move_cardinal(self, direction: Cardinal, amount: i32) -> Self {
+        Position::move_cardinal(self, direction, amount)
+    }
+    fn move_by(self, offset: Self, times: i32) -> Self {
+        Self {
+            east: self.east + offset.east * times,
+            north: self.north + offset.north * times,
+        }
+    }
+    fn turn(self, right: bool, degrees: i32) -> Option<Self> {
+        self.rotate_degrees(right, degrees)
     }
 }
 
-trait Rotatable {
+impl Vector {
+    pub fn get_manhattan_distance(&self) -> f64 {
+        self.east.abs() + self.north.abs()
+    }
+    pub fn round(&self) -> Position {
+        Position {
+            east: self.east.round() as i32,
+            north: self.north.round() as i32,
+        }
+    }
+}
+impl From<Position> for Vector {
+    fn from(position: Position) -> Self {
+        Self {
+            east: position.east.into(),
+            north: position.north.into(),
+        }
+    }
+}
+impl Coordinates for Vector {
+    fn new(east: i32, north: i32) -> Self {
+        Position { east, north }.into()
+    }
+    fn to_vector(self) -> Vector {
+        self
+    }
+    fn move_cardinal(self, direction: Cardinal, amount: i32) -> Self {
+        self.move_by(Position::from(direction).into(), amount)
+    }
+    fn move_by(self, offset: Self, times: i32) -> Self {
+        let times = f64::from(times);
+        Self {
+            east: self.east + offset.east * times,
+            north: self.north + offset.north * times,
+        }
+    }
+    // Quarter turns only swap and negate the components, so they stay exact.
+    fn turn(self, right: bool, degrees: i32) -> Option<Self> {
+        if degrees % 90 == 0 {
+            return self.rotate_degrees(right, degrees);
+        }
+        let angle = f64::from(if right { degrees } else { -degrees }).to_radians();
+        let (sin, cos) = angle.sin_cos();
+        Some(Self {
+            east: self.east * cos + self.north * sin,
+            north: self.north * cos - self.east * sin,
+        })
+    }
+}
+
+pub trait Rotatable {
     fn rotate_right(self) -> Self;
     fn rotate_left(self) -> Self;
 }
@@ -213,17 +652,34 @@ impl Rotatable for Position {
         }
     }
 }
-trait RotatableExt: Rotatable + Sized {
-    fn rotate_degrees(mut self, right: bool, degrees: i32) -> Self {
-        assert_eq!(degrees % 90, 0, "Only multiples of 90° are supported");
-        for _ in 0..degrees / 90 {
+impl Rotatable for Vector {
+    fn rotate_right(self) -> Self {
+        Self {
+            east: self.north,
+            north: -self.east,
+        }
+    }
+    fn rotate_left(self) -> Self {
+        Self {
+            east: -self.north,
+            north: self.east,
+        }
+    }
+}
+pub trait RotatableExt: Rotatable + Sized {
+    // `None` unless `degrees` is a multiple of 90°.
+    fn rotate_degrees(mut self, right: bool, degrees: i32) -> Option<Self> {
+        if degrees % 90 != 0 {
+            return None;
+        }
+        for _ in 0..degrees.rem_euclid(360) / 90 {
             self = if right {
                 self.rotate_right()
             } else {
                 self.rotate_left()
             };
         }
-        self
+        Some(self)
     }
 }
 impl<T: Rotatable> RotatableExt for T {}
@@ -289,18 +745,18 @@ F11"
     #[test]
     fn example_1() {
         let mut ship = HeadedShip::new();
-        ship = ship.move_relative(Relative::Forward, 10);
+        ship = ship.move_relative(Relative::Forward, 10).unwrap();
         assert_eq!(ship.position.east, 10);
         assert_eq!(ship.position.north, 0);
         ship = ship.move_cardinal(Cardinal::North, 3);
         assert_eq!(ship.position.east, 10);
         assert_eq!(ship.position.north, 3);
-        ship = ship.move_relative(Relative::Forward, 7);
+        ship = ship.move_relative(Relative::Forward, 7).unwrap();
         assert_eq!(ship.position.east, 17);
         assert_eq!(ship.position.north, 3);
-        ship = ship.move_relative(Relative::Right, 90);
-        assert_eq!(ship.heading, Cardinal::South);
-        ship = ship.move_relative(Relative::Forward, 11);
+        ship = ship.move_relative(Relative::Right, 90).unwrap();
+        assert_eq!(ship.heading, Position::from(Cardinal::South));
+        ship = ship.move_relative(Relative::Forward, 11).unwrap();
         assert_eq!(ship.position.east, 17);
         assert_eq!(ship.position.north, -8);
     }
@@ -313,19 +769,19 @@ F11"
     #[test]
     fn example_2() {
         let mut ship = WaypontedShip::new();
-        ship = ship.move_relative(Relative::Forward, 10);
+        ship = ship.move_relative(Relative::Forward, 10).unwrap();
         assert_eq!(ship.position.east, 100);
         assert_eq!(ship.position.north, 10);
         ship = ship.move_cardinal(Cardinal::North, 3);
         assert_eq!(ship.waypoint.east, 10);
         assert_eq!(ship.waypoint.north, 4);
-        ship = ship.move_relative(Relative::Forward, 7);
+        ship = ship.move_relative(Relative::Forward, 7).unwrap();
         assert_eq!(ship.position.east, 170);
         assert_eq!(ship.position.north, 38);
-        ship = ship.move_relative(Relative::Right, 90);
+        ship = ship.move_relative(Relative::Right, 90).unwrap();
         assert_eq!(ship.waypoint.east, 4);
         assert_eq!(ship.waypoint.north, -10);
-        ship = ship.move_relative(Relative::Forward, 11);
+        ship = ship.move_relative(Relative::Forward, 11).unwrap();
         assert_eq!(ship.position.east, 214);
         assert_eq!(ship.position.north, -72);
     }
@@ -334,4 +790,173 @@ F11"
     fn part_2() {
         assert_eq!(include_str!("inputs/day_12").part_2(), 106_860);
     }
+
+    #[test]
+    fn any_angle() {
+        let instructions = parsers::input(include_str!("inputs/day_12")).unwrap();
+        let headed = instructions.iter().fold(
+            HeadedShip::<Vector>::default(),
+            |ship, &(direction, amount)| ship.do_move(direction, amount).unwrap(),
+        );
+        assert_eq!(headed.position.get_manhattan_distance(), 1457.);
+        let wayponted = instructions.iter().fold(
+            WaypontedShip::<Vector>::default(),
+            |ship, &(direction, amount)| ship.do_move(direction, amount).unwrap(),
+        );
+        assert_eq!(wayponted.position.get_manhattan_distance(), 106_860.);
+
+        let ship = HeadedShip::<Vector>::default()
+            .move_relative(Relative::Left, 45)
+            .unwrap()
+            .move_relative(Relative::Forward, 10)
+            .unwrap();
+        assert!((ship.position.east - 50f64.sqrt()).abs() < 1e-9);
+        assert!((ship.position.north - 50f64.sqrt()).abs() < 1e-9);
+        let ship = ship
+            .move_relative(Relative::Right, 135)
+            .unwrap()
+            .move_relative(Relative::Forward, 3)
+            .unwrap();
+        assert_eq!(ship.position.round(), Position { east: 7, north: 4 });
+
+        let ship = WaypontedShip::<Vector>::default()
+            .move_relative(Relative::Right, 30)
+            .unwrap()
+            .move_relative(Relative::Right, 60)
+            .unwrap()
+            .move_relative(Relative::Forward, 1)
+            .unwrap();
+        assert_eq!(
+            ship.position.round(),
+            Position {
+                east: 1,
+                north: -10
+            }
+        );
+        let ship = WaypontedShip::<Vector>::default()
+            .move_relative(Relative::Left, 270)
+            .unwrap();
+        assert_eq!(ship.waypoint, Vector::new(1, -10));
+    }
+
+    #[test]
+    fn quarter_turns() {
+        let ship = HeadedShip::new();
+        let turned = ship.do_move(Direction::Relative(Relative::Left), -90);
+        assert_eq!(turned.unwrap().heading, Position::from(Cardinal::South));
+        let turned = ship.do_move(Direction::Relative(Relative::Right), 450);
+        assert_eq!(turned.unwrap().heading, Position::from(Cardinal::South));
+        assert_eq!(ship.do_move(Direction::Relative(Relative::Right), 45), None);
+        assert_eq!(WaypontedShip::new().move_relative(Relative::Left, 30), None);
+
+        let instructions = parsers::input("F10\nR45\nF1").unwrap();
+        let mut voyage = Voyage::new(ship);
+        assert_eq!(Voyage::sail(ship, instructions.clone()), None);
+        assert_eq!(
+            voyage.record(Direction::Relative(Relative::Right), 45),
+            None
+        );
+        assert_eq!(voyage.ships().len(), 1);
+    }
+
+    #[test]
+    fn voyage() {
+        let instructions = parsers::input("F10\nN3\nF7\nR90\nF11").unwrap();
+        let voyage = Voyage::sail(HeadedShip::new(), instructions.clone()).unwrap();
+        assert_eq!(voyage.ships().len(), 6);
+        assert_eq!(
+            voyage.positions().collect::<Vec<_>>(),
+            vec![
+                Position::new(0, 0),
+                Position::new(10, 0),
+                Position::new(10, 3),
+                Position::new(17, 3),
+                Position::new(17, 3),
+                Position::new(17, -8),
+            ]
+        );
+        assert_eq!(voyage.waypoints().count(), 0);
+        assert_eq!(
+            voyage.bounding_box(),
+            (Vector::new(0, -8), Vector::new(17, 3))
+        );
+        assert_eq!(voyage.distance(), 31.);
+        assert_eq!(voyage.farthest(), Position::new(17, -8));
+        assert_eq!(
+            voyage.to_ascii(18, 12),
+            "          ########
+          #      #
+          #      #
+S##########      #
+                 #
+                 #
+                 #
+                 #
+                 #
+                 #
+                 #
+                 E"
+        );
+        assert_eq!(voyage.to_ascii(0, 12), "");
+        assert_eq!(voyage.to_ascii(18, 0), "");
+        assert_eq!(voyage.to_ascii(1, 1), "E");
+
+        let voyage = Voyage::sail(WaypontedShip::new(), instructions).unwrap();
+        assert_eq!(voyage.end().position, Position::new(214, -72));
+        assert_eq!(voyage.farthest(), Position::new(214, -72));
+        assert_eq!(voyage.waypoints().last(), Some(Position::new(218, -82)));
+        assert_eq!(
+            voyage.bounding_box(),
+            (Vector::new(0, -72), Vector::new(214, 38))
+        );
+        let svg = voyage.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("points=\"0,0 100,-10 100,-10 170,-38 170,-38 214,72\""));
+        assert_eq!(svg.matches("<circle").count(), 8);
+    }
+
+    #[test]
+    fn plan_headed() {
+        let ship = HeadedShip::new();
+        let instructions = plan(ship, Position::new(-3, 5)).unwrap();
+        assert_eq!(format_instructions(&instructions), "W3\nN5");
+        assert_eq!(
+            plan(ship, Position::new(7, 0)),
+            Some(vec![(Direction::Cardinal(Cardinal::East), 7)])
+        );
+        assert_eq!(plan(ship, Position::new(0, 0)), Some(vec![]));
+        let ship = ship.move_relative(Relative::Left, 90).unwrap();
+        assert_eq!(plan(ship, Position::new(0, 4)).unwrap().len(), 1);
+        let text = format_instructions(&plan(ship, Position::new(-120, 37)).unwrap());
+        assert_eq!(text.part_1(), 157);
+    }
+
+    #[test]
+    fn plan_wayponted() {
+        let ship = WaypontedShip::new();
+        assert_eq!(
+            plan(ship, Position::new(30, 3)),
+            Some(vec![(Direction::Relative(Relative::Forward), 3)])
+        );
+        assert_eq!(
+            plan(ship, Position::new(-20, -2)),
+            Some(vec![
+                (Direction::Relative(Relative::Right), 180),
+                (Direction::Relative(Relative::Forward), 2)
+            ])
+        );
+        assert_eq!(
+            format_instructions(&plan(ship, Position::new(12, -8)).unwrap()),
+            "W7\nS3\nF4"
+        );
+        for &(east, north) in &[(1, 0), (-5, 17), (100, -100), (-7, -91)] {
+            let target = Position::new(east, north);
+            let instructions = plan(ship, target).unwrap();
+            assert!(instructions.len() <= 3);
+            assert_eq!(
+                format_instructions(&instructions).part_2(),
+                target.get_manhattan_distance()
+            );
+        }
+    }
 }
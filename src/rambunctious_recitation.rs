@@ -1,6 +1,9 @@
 //! Day 15
 
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    iter::FromIterator,
+};
 
 trait Solution {
     fn part_1(&self) -> usize;
@@ -13,13 +16,15 @@ impl Solution for str {
             .expect("Empty input")
     }
     fn part_2(&self) -> usize {
-        speak_numbers(parsers::input(self).expect("Failed to parse the input"))
-            .nth(30_000_000 - 1)
-            .expect("Empty input")
+        spoken_at(
+            &parsers::input(self).expect("Failed to parse the input"),
+            30_000_000,
+        )
+        .expect("Empty input")
     }
 }
 
-fn speak_numbers(initial: Vec<usize>) -> impl Iterator<Item = usize> {
+pub fn speak_numbers(initial: Vec<usize>) -> impl Iterator<Item = usize> {
     (0..).scan(
         (HashMap::new(), None),
         move |(last_turn_seen, previous), turn| {
@@ -40,6 +45,124 @@ fn speak_numbers(initial: Vec<usize>) -> impl Iterator<Item = usize> {
     )
 }
 
+// Same as `speak_numbers`, stopping after `n_turns`. Every number spoken
+// after the starting ones is smaller than the turn, so the turns they were
+// last spoken fit in a vector.
+pub fn speak_numbers_dense(initial: Vec<usize>, n_turns: u32) -> impl Iterator<Item = usize> {
+    let n_turns = n_turns as usize;
+    let size = initial
+        .iter()
+        .map(|v| v + 1)
+        .max()
+        .unwrap_or(0)
+        .max(n_turns);
+    (0..n_turns).scan(
+        (vec![0u32; size], None),
+        move |(last_turn_seen, previous), turn| {
+            let current = if turn < initial.len() {
+                initial[turn]
+            } else {
+                match last_turn_seen[(*previous)?] {
+                    0 => 0,
+                    seen => turn - seen as usize,
+                }
+            };
+            if let Some(previous) = previous {
+                last_turn_seen[*previous] = turn as u32;
+            }
+            *previous = Some(current);
+            Some(current)
+        },
+    )
+}
+
+// The number spoken at the given turn, counting from 1. `None` past turn
+// `u32::MAX`, as the turns are stored as `u32`.
+pub fn spoken_at(initial: &[usize], turn: usize) -> Option<usize> {
+    let (&last, previous) = initial.split_last()?;
+    if turn <= initial.len() {
+        return initial.get(turn.checked_sub(1)?).copied();
+    }
+    if turn > u32::MAX as usize {
+        return None;
+    }
+    let size = initial.iter().map(|v| v + 1).max().unwrap().max(turn);
+    let mut last_turn_seen = vec![0u32; size];
+    for (ix, &v) in previous.iter().enumerate() {
+        last_turn_seen[v] = ix as u32 + 1;
+    }
+    let mut current = last;
+    for turn in initial.len()..turn {
+        let seen = last_turn_seen[current];
+        last_turn_seen[current] = turn as u32;
+        current = if seen == 0 { 0 } else { turn - seen as usize };
+    }
+    Some(current)
+}
+
+// Statistics over the numbers spoken in a game, gathered in a single pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Statistics {
+    turns: usize,
+    first_turn: HashMap<usize, usize>,
+    last_turn: HashMap<usize, usize>,
+    gaps: BTreeMap<usize, usize>,
+    zeros: usize,
+    largest: Option<usize>,
+}
+
+impl Statistics {
+    pub fn of(initial: Vec<usize>, n_turns: u32) -> Self {
+        speak_numbers_dense(initial, n_turns).collect()
+    }
+    pub fn push(&mut self, value: usize) {
+        self.turns += 1;
+        self.first_turn.entry(value).or_insert(self.turns);
+        if let Some(last) = self.last_turn.insert(value, self.turns) {
+            *self.gaps.entry(self.turns - last).or_default() += 1;
+        }
+        if value == 0 {
+            self.zeros += 1;
+        }
+        self.largest = self.largest.max(Some(value));
+    }
+
+    pub fn turns(&self) -> usize {
+        self.turns
+    }
+    // Turn, counting from 1, at which `value` was first spoken.
+    pub fn first_turn(&self, value: usize) -> Option<usize> {
+        self.first_turn.get(&value).copied()
+    }
+    pub fn first_turns(&self) -> &HashMap<usize, usize> {
+        &self.first_turn
+    }
+    // How many times each number of turns passed between two consecutive
+    // occurrences of the same value.
+    pub fn gaps(&self) -> &BTreeMap<usize, usize> {
+        &self.gaps
+    }
+    pub fn zeros(&self) -> usize {
+        self.zeros
+    }
+    pub fn largest(&self) -> Option<usize> {
+        self.largest
+    }
+    pub fn distinct(&self) -> usize {
+        self.first_turn.len()
+    }
+}
+
+impl FromIterator<usize> for Statistics {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut statistics = Self::default();
+        for value in iter {
+            statistics.push(value);
+        }
+        statistics
+    }
+}
+
 mod parsers {
     use std::str::FromStr;
 
@@ -148,4 +271,64 @@ mod tests {
     fn part_2() {
         assert_eq!(include_str!("inputs/day_15").part_2(), 436);
     }
+
+    #[test]
+    fn dense() {
+        for initial in &[vec![0, 3, 6], vec![1, 3, 2], vec![3, 1, 2], vec![20, 0]] {
+            let expected: Vec<_> = speak_numbers(initial.clone()).take(5000).collect();
+            assert_eq!(
+                speak_numbers_dense(initial.clone(), 5000).collect::<Vec<_>>(),
+                expected
+            );
+            for turn in (1..=5000).step_by(97) {
+                assert_eq!(spoken_at(initial, turn), Some(expected[turn - 1]));
+            }
+        }
+        assert_eq!(
+            speak_numbers_dense(vec![0, 3, 6], 2).collect::<Vec<_>>(),
+            vec![0, 3]
+        );
+        assert_eq!(spoken_at(&[0, 3, 6], 0), None);
+        assert_eq!(spoken_at(&[], 10), None);
+        assert_eq!(spoken_at(&[0, 3, 6], usize::MAX), None);
+        assert_eq!(spoken_at(&[0, 3, 6], 3), Some(6));
+        assert_eq!(speak_numbers_dense(vec![], 10).next(), None);
+    }
+
+    #[test]
+    fn statistics() {
+        let statistics = Statistics::of(vec![0, 3, 6], 10);
+        assert_eq!(statistics.turns(), 10);
+        assert_eq!(
+            statistics.first_turns(),
+            &[(0, 1), (3, 2), (6, 3), (1, 7), (4, 9)]
+                .iter()
+                .copied()
+                .collect()
+        );
+        assert_eq!(statistics.first_turn(4), Some(9));
+        assert_eq!(statistics.first_turn(2), None);
+        assert_eq!(
+            statistics.gaps(),
+            &[(1, 1), (2, 1), (3, 2), (4, 1)].iter().copied().collect()
+        );
+        assert_eq!(statistics.zeros(), 4);
+        assert_eq!(statistics.largest(), Some(6));
+        assert_eq!(statistics.distinct(), 5);
+        assert_eq!(Statistics::of(vec![], 10), Statistics::default());
+    }
+
+    #[test]
+    fn statistics_long_run() {
+        let statistics = Statistics::of(vec![1, 3, 2], 2020);
+        assert_eq!(statistics.turns(), 2020);
+        assert_eq!(
+            statistics.gaps().values().sum::<usize>(),
+            2020 - statistics.distinct()
+        );
+        assert_eq!(
+            statistics.largest(),
+            speak_numbers(vec![1, 3, 2]).take(2020).max()
+        );
+    }
 }
@@ -1,6 +1,8 @@
 //! Day 13
 
-use std::ops::{Rem, Sub};
+use std::ops::Range;
+
+use num_traits::PrimInt;
 
 trait Solution {
     fn part_1(&self) -> u32;
@@ -8,75 +10,178 @@ trait Solution {
 }
 impl Solution for str {
     fn part_1(&self) -> u32 {
-        let (threshold, candidates) = parsers::input(self).expect("Failed to parse the input");
-        let id = least_multiple_above(candidates.iter().flatten(), threshold).expect("No ID found");
-        let remaining = wait_time(threshold, *id);
-        id * remaining
+        let (timestamp, buses): (u32, _) = parsers::input(self).expect("Failed to parse the input");
+        let schedule = Schedule::new(timestamp, buses);
+        let (id, departure) = schedule.earliest_bus().expect("No ID found");
+        id * (departure - timestamp)
     }
     fn part_2(&self) -> i64 {
-        let (offsets, ids) =
-            sparse_offsets(parsers::input(self).expect("Failed to parse the input").1);
-        chinese_remainder_inv(&offsets, &ids).expect("IDs not pairwise coprime")
+        let (timestamp, buses): (i64, _) = parsers::input(self).expect("Failed to parse the input");
+        Schedule::new(timestamp, buses)
+            .aligned()
+            .expect("No alignment found")
     }
 }
 
-fn wait_time<T>(from: T, period: T) -> T
-where
-    T: Sub<Output = T> + Rem<Output = T> + Copy,
-{
-    period - (from % period)
+// Buses are identified by their period and listed at their offset in the
+// schedule, `None` marking out of service slots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule<T> {
+    timestamp: T,
+    buses: Vec<Option<T>>,
 }
 
-pub fn least_multiple_above<'a>(
-    candidates: impl IntoIterator<Item = &'a u32>,
-    threshold: u32,
-) -> Option<&'a u32> {
-    candidates
-        .into_iter()
-        .min_by_key(|v| wait_time(threshold, **v))
-}
+impl<T: PrimInt> Schedule<T> {
+    pub fn new(timestamp: T, buses: Vec<Option<T>>) -> Self {
+        Self { timestamp, buses }
+    }
+    pub fn timestamp(&self) -> T {
+        self.timestamp
+    }
+    // Buses in service along with their offset.
+    pub fn buses(&self) -> impl Iterator<Item = (usize, T)> + '_ {
+        self.buses
+            .iter()
+            .enumerate()
+            .filter_map(|(offset, bus)| bus.map(|bus| (offset, bus)))
+    }
 
-fn sparse_offsets(values: impl IntoIterator<Item = Option<i64>> + Clone) -> (Vec<i64>, Vec<i64>) {
-    values
-        .into_iter()
-        .enumerate()
-        .filter_map(|(i, v)| v.map(|v| (i as i64, v)))
-        .unzip()
+    // Earliest departure of `bus` at or after `t`. `None` if the bus isn't
+    // positive, `t` is negative, or the departure doesn't fit in `T`.
+    pub fn next_departure(&self, bus: T, t: T) -> Option<T> {
+        if bus <= T::zero() || t < T::zero() {
+            return None;
+        }
+        let remainder = t % bus;
+        if remainder.is_zero() {
+            Some(t)
+        } else {
+            t.checked_add(&(bus - remainder))
+        }
+    }
+    // First bus leaving at or after the timestamp, along with its departure.
+    pub fn earliest_bus(&self) -> Option<(T, T)> {
+        self.buses()
+            .filter_map(|(_, bus)| Some((bus, self.next_departure(bus, self.timestamp)?)))
+            .min_by_key(|&(_, departure)| departure)
+    }
+    // Earliest timestamp `t` such that every `bus` departs at `t + offset`.
+    // Periods need not be coprime; `None` if there is no such timestamp or it
+    // doesn't fit in `T`.
+    pub fn earliest_alignment(constraints: &[(T, T)]) -> Option<T> {
+        let (buses, offsets): (Vec<_>, Vec<_>) = constraints.iter().copied().unzip();
+        chinese_remainder_inv(&offsets, &buses)
+    }
+    // Earliest timestamp at which every bus departs at its offset.
+    pub fn aligned(&self) -> Option<T> {
+        let constraints = self
+            .buses()
+            .map(|(offset, bus)| Some((bus, T::from(offset)?)))
+            .collect::<Option<Vec<_>>>()?;
+        Self::earliest_alignment(&constraints)
+    }
+    // Departures as `(time, bus)` within `window`, sorted by time.
+    pub fn departures(&self, window: Range<T>) -> Vec<(T, T)> {
+        let mut departures = Vec::new();
+        for (_, bus) in self.buses() {
+            let mut t = match self.next_departure(bus, window.start.max(T::zero())) {
+                Some(t) => t,
+                None => continue,
+            };
+            while t < window.end {
+                departures.push((t, bus));
+                t = match t.checked_add(&bus) {
+                    Some(t) => t,
+                    None => break,
+                };
+            }
+        }
+        departures.sort_unstable();
+        departures
+    }
 }
-fn egcd(a: i64, b: i64) -> (i64, i64, i64) {
-    if a == 0 {
-        (b, 0, 1)
+
+// Modular arithmetic on `[0, n)` that never overflows `T`.
+fn add_mod<T: PrimInt>(a: T, b: T, n: T) -> T {
+    if a >= n - b {
+        a - (n - b)
     } else {
-        let (g, x, y) = egcd(b % a, a);
-        (g, y - (b / a) * x, x)
+        a + b
     }
 }
-fn mod_inv(x: i64, n: i64) -> Option<i64> {
-    let (g, x, _) = egcd(x, n);
-    if g == 1 {
-        Some((x % n + n) % n)
+fn sub_mod<T: PrimInt>(a: T, b: T, n: T) -> T {
+    if a >= b {
+        a - b
     } else {
-        None
+        n - (b - a)
     }
 }
-fn chinese_remainder(residues: &[i64], modulii: &[i64]) -> Option<i64> {
-    let prod = modulii.iter().product::<i64>();
-
-    let mut sum = 0;
-
+fn mul_mod<T: PrimInt>(mut a: T, mut b: T, n: T) -> T {
+    let mut product = T::zero();
+    while !b.is_zero() {
+        if b & T::one() == T::one() {
+            product = add_mod(product, a, n);
+        }
+        a = add_mod(a, a, n);
+        b = b >> 1;
+    }
+    product
+}
+// The gcd of `x` and `n`, along with the inverse of `x / gcd` modulo
+// `n / gcd`.
+fn egcd<T: PrimInt>(x: T, n: T) -> (T, T) {
+    let (mut r0, mut r1) = (n, x % n);
+    let (mut t0, mut t1) = (T::zero(), T::one() % n);
+    while !r1.is_zero() {
+        let q = r0 / r1;
+        let r2 = r0 - q * r1;
+        let t2 = sub_mod(t0, mul_mod(q % n, t1, n), n);
+        r0 = r1;
+        r1 = r2;
+        t0 = t1;
+        t1 = t2;
+    }
+    (r0, t0 % (n / r0))
+}
+// Smallest non-negative `x` congruent to each residue modulo its modulus.
+// Moduli need not be coprime; `None` if there is no solution, it doesn't fit
+// in `T`, or a modulus isn't positive.
+fn chinese_remainder<T: PrimInt>(residues: &[T], modulii: &[T]) -> Option<T> {
+    let (mut x, mut lcm) = (T::zero(), Some(T::one()));
     for (&residue, &modulus) in residues.iter().zip(modulii) {
-        let p = prod / modulus;
-        sum += residue * mod_inv(p, modulus)? * p
+        if modulus <= T::zero() || residue < T::zero() {
+            return None;
+        }
+        // The step only needs to fit if another constraint follows.
+        let step = lcm?;
+        // Find `k` such that `x + k * step` has the right residue.
+        let residue = residue % modulus;
+        let (g, inv) = egcd(step % modulus, modulus);
+        let diff = sub_mod(residue, x % modulus, modulus);
+        if !(diff % g).is_zero() {
+            return None;
+        }
+        let reduced = modulus / g;
+        let k = mul_mod(diff / g, inv, reduced);
+        x = x.checked_add(&k.checked_mul(&step)?)?;
+        lcm = (step / g).checked_mul(&modulus);
     }
-
-    Some(sum % prod)
+    Some(x)
 }
-fn chinese_remainder_inv(inv_residues: &[i64], modulii: &[i64]) -> Option<i64> {
-    let residues: Vec<_> = inv_residues
+// Same as `chinese_remainder`, with `x + inv_residue` divisible by each
+// modulus.
+fn chinese_remainder_inv<T: PrimInt>(inv_residues: &[T], modulii: &[T]) -> Option<T> {
+    let residues = inv_residues
         .iter()
         .zip(modulii)
-        .map(|(residue, modulus)| modulus - residue)
-        .collect();
+        .map(|(&residue, &modulus)| {
+            if modulus <= T::zero() || residue < T::zero() {
+                None
+            } else {
+                Some((modulus - residue % modulus) % modulus)
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
     chinese_remainder(&residues, modulii)
 }
 
@@ -133,8 +238,9 @@ mod tests {
 
     #[test]
     fn example_1() {
-        assert_eq!(least_multiple_above(&[7, 13, 59, 31, 19], 939), Some(&59));
-        assert_eq!(wait_time(939, 59), 5);
+        let schedule = Schedule::new(939, [7, 13, 59, 31, 19].iter().copied().map(Some).collect());
+        assert_eq!(schedule.earliest_bus(), Some((59, 944)));
+        assert_eq!(schedule.next_departure(59, 939), Some(944));
     }
 
     #[test]
@@ -191,4 +297,79 @@ mod tests {
     fn part_2() {
         assert_eq!(include_str!("inputs/day_13").part_2(), 535_296_695_251_210);
     }
+
+    #[test]
+    fn schedule() {
+        let (timestamp, buses) = parsers::input::<u32>("939\n7,13,x,x,59,x,31,19").unwrap();
+        let schedule = Schedule::new(timestamp, buses);
+        assert_eq!(
+            schedule.buses().collect::<Vec<_>>(),
+            vec![(0, 7), (1, 13), (4, 59), (6, 31), (7, 19)]
+        );
+        assert_eq!(schedule.next_departure(59, 939), Some(944));
+        assert_eq!(schedule.next_departure(7, 945), Some(945));
+        assert_eq!(schedule.earliest_bus(), Some((59, 944)));
+        assert_eq!(schedule.aligned(), Some(1_068_781));
+        assert_eq!(
+            Schedule::<u32>::earliest_alignment(&[(17, 0), (13, 2), (19, 3)]),
+            Some(3417)
+        );
+        assert_eq!(
+            Schedule::<u32>::earliest_alignment(&[(7, 0), (59, 4)]),
+            Some(350)
+        );
+        assert_eq!(
+            schedule.departures(936..945),
+            vec![(936, 13), (938, 7), (944, 59)]
+        );
+    }
+
+    #[test]
+    fn schedule_not_coprime() {
+        let schedule = Schedule::<u8>::new(0, vec![Some(4), None, Some(6)]);
+        assert_eq!(schedule.aligned(), Some(4));
+        assert_eq!(Schedule::<u8>::earliest_alignment(&[(4, 0), (6, 1)]), None);
+        assert_eq!(
+            Schedule::<u8>::earliest_alignment(&[(251, 0), (241, 1)]),
+            None
+        );
+        assert_eq!(schedule.departures(250..255), vec![(252, 4), (252, 6)]);
+        assert_eq!(schedule.next_departure(4, 252), Some(252));
+        assert_eq!(schedule.next_departure(4, 254), None);
+        assert_eq!(schedule.next_departure(0, 3), None);
+        assert_eq!(Schedule::<u8>::new(0, vec![Some(0)]).earliest_bus(), None);
+        assert_eq!(Schedule::<u8>::earliest_alignment(&[(0, 1)]), None);
+    }
+
+    #[test]
+    fn schedule_signed() {
+        let schedule = Schedule::<i8>::new(-5, vec![Some(3), Some(-2), Some(5)]);
+        assert_eq!(schedule.next_departure(3, -5), None);
+        assert_eq!(schedule.next_departure(-2, 5), None);
+        assert_eq!(schedule.earliest_bus(), None);
+        assert_eq!(schedule.aligned(), None);
+        assert_eq!(schedule.departures(-5..4), vec![(0, 3), (0, 5), (3, 3)]);
+        assert_eq!(
+            Schedule::<i8>::earliest_alignment(&[(3, 0), (5, 1)]),
+            Some(9)
+        );
+        assert_eq!(Schedule::<i8>::earliest_alignment(&[(3, 0), (5, -1)]), None);
+        assert_eq!(
+            Schedule::<i8>::earliest_alignment(&[(11, 0), (13, 1)]),
+            Some(77)
+        );
+        assert_eq!(
+            Schedule::<i8>::earliest_alignment(&[(11, 0), (13, 1), (3, 0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn schedule_input() {
+        let (timestamp, buses) = parsers::input::<u64>(include_str!("inputs/day_13")).unwrap();
+        let schedule = Schedule::new(timestamp, buses);
+        assert_eq!(schedule.aligned(), Some(535_296_695_251_210));
+        let (timestamp, buses) = parsers::input::<u32>(include_str!("inputs/day_13")).unwrap();
+        assert_eq!(Schedule::new(timestamp, buses).aligned(), None);
+    }
 }
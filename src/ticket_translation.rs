@@ -1,8 +1,10 @@
 //! Day 16
 
-use std::{cmp::PartialOrd, collections::HashSet};
+use std::{collections::HashSet, fmt, ops::RangeInclusive, str::FromStr};
 
 use itertools::Itertools;
+use nom::error::Error;
+use num_traits::PrimInt;
 
 trait Solution {
     fn part_1(&self) -> usize;
@@ -10,31 +12,19 @@ trait Solution {
 }
 impl Solution for str {
     fn part_1(&self) -> usize {
-        let (rules, _, tickets) = parsers::input(self).expect("Failed to parse the input");
-        let rules = rules.into_iter().map(|(_, rule)| rule).collect::<Vec<_>>();
-        tickets
-            .iter()
-            .flat_map(|ticket| invalid_fields(ticket, &rules))
-            .sum()
+        Notes::parse(self)
+            .expect("Failed to parse the input")
+            .report()
+            .error_rate()
     }
     fn part_2(&self) -> usize {
-        let (named_rules, your_ticket, tickets) =
-            parsers::input::<usize>(self).expect("Failed to parse the input");
-        let rules = named_rules
-            .iter()
-            .map(|(_, rule)| *rule)
-            .collect::<Vec<_>>();
-        find_fields(&tickets, &rules)
+        Notes::<usize>::parse(self)
+            .expect("Failed to parse the input")
+            .decode()
             .expect("Field mapping not found")
-            .into_iter()
-            .enumerate()
-            .filter_map(|(rule_ix, field_ix)| {
-                if named_rules[rule_ix].0.starts_with("departure") {
-                    Some(your_ticket[field_ix])
-                } else {
-                    None
-                }
-            })
+            .yours
+            .with_prefix("departure")
+            .map(|(_, value)| value)
             .product()
     }
 }
@@ -44,43 +34,53 @@ struct Range<T> {
     min: T,
     max: T,
 }
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct RangeUnion<T> {
-    first: Range<T>,
-    second: Range<T>,
+// Inclusive ranges kept sorted, disjoint and non-adjacent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalSet<T> {
+    ranges: Vec<Range<T>>,
 }
 
-fn invalid_fields<'a, T: PartialOrd>(
+fn invalid_fields<'a, T: PrimInt>(
     fields: &'a [T],
-    rules: &'a [RangeUnion<T>],
+    valid: &'a IntervalSet<T>,
 ) -> impl Iterator<Item = &'a T> {
-    fields
-        .iter()
-        .filter(move |field| !rules.into_iter().any(|rule| rule.is_valid(field)))
+    fields.iter().filter(move |field| !valid.contains(field))
 }
-fn find_fields<'a, T: PartialOrd>(
-    tickets: &[Vec<T>],
-    rules: &[RangeUnion<T>],
-) -> Option<Vec<usize>> {
+fn find_fields<T: PrimInt>(tickets: &[Vec<T>], rules: &[IntervalSet<T>]) -> Option<Vec<usize>> {
+    let valid = IntervalSet::union_all(rules);
     let valid_tickets: Vec<_> = tickets
         .iter()
-        .filter(|ticket| invalid_fields(ticket, rules).next().is_none())
+        .filter(|ticket| invalid_fields(ticket, &valid).next().is_none())
         .collect();
+    resolve_fields(field_candidates(&valid_tickets, rules)?)
+}
+// Fields each rule may describe, by index in the tickets, or None if the
+// tickets don't all have the same number of fields.
+fn field_candidates<T: PrimInt>(
+    valid_tickets: &[&Vec<T>],
+    rules: &[IntervalSet<T>],
+) -> Option<Vec<HashSet<usize>>> {
     let n_fields = valid_tickets.first().map(|t| t.len()).unwrap_or(0);
-    assert!(valid_tickets.iter().all(|ticket| ticket.len() == n_fields));
-    let mut valid_fields_per_rule = rules
-        .iter()
-        .map(|rule| {
-            (0..n_fields)
-                .filter(|&field_ix| {
-                    valid_tickets
-                        .iter()
-                        .all(|ticket| rule.is_valid(&ticket[field_ix]))
-                })
-                .collect::<HashSet<_>>()
-        })
-        .collect_vec();
-    let mut field_indices = vec![0; valid_fields_per_rule.len()];
+    if valid_tickets.iter().any(|ticket| ticket.len() != n_fields) {
+        return None;
+    }
+    Some(
+        rules
+            .iter()
+            .map(|rule| {
+                (0..n_fields)
+                    .filter(|&field_ix| {
+                        valid_tickets
+                            .iter()
+                            .all(|ticket| rule.contains(&ticket[field_ix]))
+                    })
+                    .collect::<HashSet<_>>()
+            })
+            .collect_vec(),
+    )
+}
+fn resolve_fields(mut valid_fields_per_rule: Vec<HashSet<usize>>) -> Option<Vec<usize>> {
+    let mut field_indices = vec![None; valid_fields_per_rule.len()];
     // Iteratively remove rules that identify exactly one field from the
     // candidates, until we either find a solution or end up in an undecidable
     // state.
@@ -93,45 +93,384 @@ fn find_fields<'a, T: PartialOrd>(
         for candidates in valid_fields_per_rule.iter_mut() {
             candidates.remove(&field_ix);
         }
-        field_indices[rule_ix] = field_ix;
+        field_indices[rule_ix] = Some(field_ix);
+    }
+    // Every rule must have been assigned a field, which also rules out
+    // leftover candidates.
+    field_indices.into_iter().collect()
+}
+
+// The notes taken about the tickets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notes<'a, T> {
+    pub rules: Vec<(&'a str, IntervalSet<T>)>,
+    pub yours: Vec<T>,
+    pub nearby: Vec<Vec<T>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TicketStatus<T> {
+    Valid,
+    // The values that match no rule.
+    Invalid(Vec<T>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report<'a, T> {
+    // One per nearby ticket.
+    pub statuses: Vec<TicketStatus<T>>,
+    // Fields each column may hold, in rule order.
+    pub candidates: Vec<Vec<&'a str>>,
+    // Field held by each column, if it could be determined.
+    pub mapping: Option<Vec<&'a str>>,
+    pub yours: Option<Record<'a, T>>,
+}
+
+impl<'a, T: PrimInt + FromStr> Notes<'a, T> {
+    pub fn parse(s: &'a str) -> Result<Self, Error<&'a str>> {
+        let (rules, yours, nearby) = parsers::input(s)?;
+        Ok(Self {
+            rules,
+            yours,
+            nearby,
+        })
+    }
+}
+
+impl<'a, T: PrimInt> Notes<'a, T> {
+    pub fn report(&self) -> Report<'a, T> {
+        let rules: Vec<_> = self.rules.iter().map(|(_, rule)| rule.clone()).collect();
+        let valid = IntervalSet::union_all(&rules);
+        let statuses: Vec<_> = self
+            .nearby
+            .iter()
+            .map(|ticket| {
+                let invalid: Vec<_> = invalid_fields(ticket, &valid).copied().collect();
+                if invalid.is_empty() {
+                    TicketStatus::Valid
+                } else {
+                    TicketStatus::Invalid(invalid)
+                }
+            })
+            .collect();
+        let valid_tickets: Vec<_> = self
+            .nearby
+            .iter()
+            .zip(&statuses)
+            .filter(|(_, status)| **status == TicketStatus::Valid)
+            .map(|(ticket, _)| ticket)
+            .collect();
+        let candidates_per_rule = field_candidates(&valid_tickets, &rules);
+        let n_fields = valid_tickets.first().map_or(0, |t| t.len());
+        let candidates = match &candidates_per_rule {
+            Some(candidates_per_rule) => (0..n_fields)
+                .map(|field_ix| {
+                    self.rules
+                        .iter()
+                        .zip(candidates_per_rule)
+                        .filter(|(_, fields)| fields.contains(&field_ix))
+                        .map(|((name, _), _)| *name)
+                        .collect()
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        // Each column must be held by exactly one rule.
+        let mapping = candidates_per_rule
+            .and_then(resolve_fields)
+            .filter(|field_indices| field_indices.len() == n_fields)
+            .map(|field_indices| {
+                let mut mapping = vec![""; n_fields];
+                for ((name, _), field_ix) in self.rules.iter().zip(field_indices) {
+                    mapping[field_ix] = *name;
+                }
+                mapping
+            });
+        let yours = mapping
+            .as_ref()
+            .and_then(|mapping| Record::new(mapping, &self.yours));
+        Report {
+            statuses,
+            candidates,
+            mapping,
+            yours,
+        }
     }
-    if valid_fields_per_rule.iter().any(|c| !c.is_empty()) {
-        None
+    // Your ticket and the valid nearby ones as records, if the fields can be
+    // resolved.
+    pub fn decode(&self) -> Option<Decoded<'a, T>> {
+        let report = self.report();
+        let yours = report.yours?;
+        let columns = report.mapping?;
+        // Valid nearby tickets all have one value per column, otherwise the
+        // mapping would not have been resolved.
+        let nearby = self
+            .nearby
+            .iter()
+            .zip(&report.statuses)
+            .enumerate()
+            .filter(|(_, (_, status))| **status == TicketStatus::Valid)
+            .filter_map(|(ix, (ticket, _))| Some((ix, Record::new(&columns, ticket)?)))
+            .collect();
+        Some(Decoded {
+            columns,
+            yours,
+            nearby,
+        })
+    }
+}
+
+// A ticket with its values named after their field, in ticket order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record<'a, T> {
+    fields: Vec<(&'a str, T)>,
+}
+
+// Valid tickets decoded with the resolved field mapping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded<'a, T> {
+    // Field names in ticket order.
+    pub columns: Vec<&'a str>,
+    pub yours: Record<'a, T>,
+    // Valid nearby tickets, along with their index in the notes.
+    pub nearby: Vec<(usize, Record<'a, T>)>,
+}
+
+impl<'a, T: Copy> Record<'a, T> {
+    // None if the ticket doesn't have one value per column.
+    fn new(columns: &[&'a str], ticket: &[T]) -> Option<Self> {
+        if columns.len() != ticket.len() {
+            return None;
+        }
+        Some(Self {
+            fields: columns
+                .iter()
+                .copied()
+                .zip(ticket.iter().copied())
+                .collect(),
+        })
+    }
+    pub fn get(&self, name: &str) -> Option<T> {
+        self.fields()
+            .find(|&(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+    pub fn fields(&self) -> impl Iterator<Item = (&'a str, T)> + '_ {
+        self.fields.iter().copied()
+    }
+    pub fn with_prefix<'p>(&'p self, prefix: &'p str) -> impl Iterator<Item = (&'a str, T)> + 'p {
+        self.fields()
+            .filter(move |(name, _)| name.starts_with(prefix))
+    }
+}
+
+impl<'a, T: Copy> Decoded<'a, T> {
+    // Your ticket first, then the nearby ones.
+    pub fn records(&self) -> impl Iterator<Item = &Record<'a, T>> {
+        std::iter::once(&self.yours).chain(self.nearby.iter().map(|(_, record)| record))
+    }
+    pub fn filter<'p>(
+        &'p self,
+        predicate: impl Fn(&Record<'a, T>) -> bool + 'p,
+    ) -> impl Iterator<Item = &'p Record<'a, T>> {
+        self.records().filter(move |record| predicate(record))
+    }
+    // Values of a field across all the records.
+    pub fn column(&self, name: &str) -> Option<Vec<T>> {
+        self.records().map(|record| record.get(name)).collect()
+    }
+}
+
+impl<T: Copy + fmt::Display> Decoded<'_, T> {
+    // One row per ticket, yours first, with a leading column identifying it.
+    pub fn to_csv(&self) -> String {
+        let row = |id: String, record: &Record<T>| {
+            std::iter::once(id)
+                .chain(self.columns.iter().map(|name| {
+                    record
+                        .get(name)
+                        .map_or_else(String::new, |value| value.to_string())
+                }))
+                .join(",")
+        };
+        std::iter::once(
+            std::iter::once("ticket")
+                .chain(self.columns.iter().copied())
+                .map(csv_field)
+                .join(","),
+        )
+        .chain(std::iter::once(row("yours".to_owned(), &self.yours)))
+        .chain(
+            self.nearby
+                .iter()
+                .map(|(ix, record)| row(ix.to_string(), record)),
+        )
+        .map(|line| line + "\n")
+        .collect()
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
     } else {
-        Some(field_indices)
+        s.to_owned()
     }
 }
 
-impl<T: PartialOrd> Range<T> {
-    fn is_valid(&self, v: &T) -> bool {
-        &self.min <= v && v <= &self.max
+impl<T: PrimInt> Report<'_, T> {
+    // Sum of the values that match no rule.
+    pub fn error_rate(&self) -> T {
+        self.statuses
+            .iter()
+            .flat_map(|status| match status {
+                TicketStatus::Valid => &[][..],
+                TicketStatus::Invalid(values) => values,
+            })
+            .fold(T::zero(), |sum, &v| sum + v)
     }
 }
-impl<T: PartialOrd> RangeUnion<T> {
-    fn is_valid(&self, v: &T) -> bool {
-        self.first.is_valid(v) || self.second.is_valid(v)
+
+impl<T: Copy + fmt::Display> fmt::Display for Report<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (ix, status) in self.statuses.iter().enumerate() {
+            match status {
+                TicketStatus::Valid => writeln!(f, "ticket {}: valid", ix)?,
+                TicketStatus::Invalid(values) => writeln!(
+                    f,
+                    "ticket {}: invalid ({})",
+                    ix,
+                    values.iter().map(ToString::to_string).join(", ")
+                )?,
+            }
+        }
+        for (ix, candidates) in self.candidates.iter().enumerate() {
+            writeln!(f, "column {}: {}", ix, candidates.join(", "))?;
+        }
+        match (&self.mapping, &self.yours) {
+            (Some(mapping), Some(yours)) => {
+                writeln!(f, "mapping: {}", mapping.join(", "))?;
+                write!(
+                    f,
+                    "your ticket: {}",
+                    yours
+                        .fields()
+                        .map(|(name, value)| format!("{}={}", name, value))
+                        .join(", ")
+                )
+            }
+            _ => write!(f, "mapping: unresolved"),
+        }
+    }
+}
+
+impl<T: PrimInt> IntervalSet<T> {
+    pub fn new(ranges: impl IntoIterator<Item = RangeInclusive<T>>) -> Self {
+        let mut ranges: Vec<_> = ranges
+            .into_iter()
+            .filter(|r| r.start() <= r.end())
+            .map(|r| Range {
+                min: *r.start(),
+                max: *r.end(),
+            })
+            .collect();
+        ranges.sort_unstable_by_key(|r| r.min);
+        let mut normalized: Vec<Range<T>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match normalized.last_mut() {
+                Some(last) if last.max == T::max_value() || range.min <= last.max + T::one() => {
+                    last.max = last.max.max(range.max);
+                }
+                _ => normalized.push(range),
+            }
+        }
+        Self { ranges: normalized }
+    }
+    pub fn empty() -> Self {
+        Self { ranges: Vec::new() }
+    }
+    pub fn union_all<'a>(sets: impl IntoIterator<Item = &'a Self>) -> Self
+    where
+        T: 'a,
+    {
+        Self::new(sets.into_iter().flat_map(Self::ranges))
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = RangeInclusive<T>> + '_ {
+        self.ranges.iter().map(|r| r.min..=r.max)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+    pub fn contains(&self, v: &T) -> bool {
+        let ix = self.ranges.partition_point(|r| r.max < *v);
+        self.ranges.get(ix).is_some_and(|r| r.min <= *v)
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(self.ranges().chain(other.ranges()))
+    }
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while let (Some(a), Some(b)) = (self.ranges.get(i), other.ranges.get(j)) {
+            let (min, max) = (a.min.max(b.min), a.max.min(b.max));
+            if min <= max {
+                ranges.push(Range { min, max });
+            }
+            if a.max < b.max {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        Self { ranges }
+    }
+    pub fn complement(&self) -> Self {
+        let mut ranges = Vec::new();
+        let mut next = Some(T::min_value());
+        for range in &self.ranges {
+            if let Some(min) = next {
+                if min < range.min {
+                    ranges.push(Range {
+                        min,
+                        max: range.min - T::one(),
+                    });
+                }
+            }
+            next = range.max.checked_add(&T::one());
+        }
+        if let Some(min) = next {
+            ranges.push(Range {
+                min,
+                max: T::max_value(),
+            });
+        }
+        Self { ranges }
     }
 }
 
 mod parsers {
-    use std::str::FromStr;
+    use std::{ops::RangeInclusive, str::FromStr};
 
     use nom::{
         bytes::complete::{tag, take_till},
         character::complete::{char, line_ending},
+        combinator::map,
         error::Error,
         multi::separated_list1,
         sequence::{separated_pair, terminated},
         IResult,
     };
+    use num_traits::PrimInt;
 
     use crate::parsers::{finished_parser, integer};
 
-    use super::{Range, RangeUnion};
+    use super::IntervalSet;
 
-    pub fn input<T: FromStr>(
-        s: &str,
-    ) -> Result<(Vec<(&str, RangeUnion<T>)>, Vec<T>, Vec<Vec<T>>), Error<&str>> {
+    pub type Input<'a, T> = (Vec<(&'a str, IntervalSet<T>)>, Vec<T>, Vec<Vec<T>>);
+
+    pub fn input<T: FromStr + PrimInt>(s: &str) -> Result<Input<'_, T>, Error<&str>> {
         finished_parser(move |s| {
             let (s, rules) = terminated(separated_list1(line_ending, rule), line_ending)(s)?;
             let (s, _) = line_ending(s)?;
@@ -144,16 +483,15 @@ mod parsers {
         })(s)
     }
 
-    fn rule<T: FromStr>(s: &str) -> IResult<&str, (&str, RangeUnion<T>)> {
-        separated_pair(take_till(|c| c == ':'), tag(": "), range_union)(s)
+    fn rule<T: FromStr + PrimInt>(s: &str) -> IResult<&str, (&str, IntervalSet<T>)> {
+        separated_pair(take_till(|c| c == ':'), tag(": "), interval_set)(s)
     }
-    fn range_union<T: FromStr>(s: &str) -> IResult<&str, RangeUnion<T>> {
-        let (s, (first, second)) = separated_pair(range, tag(" or "), range)(s)?;
-        Ok((s, RangeUnion { first, second }))
+    fn interval_set<T: FromStr + PrimInt>(s: &str) -> IResult<&str, IntervalSet<T>> {
+        map(separated_list1(tag(" or "), range), IntervalSet::new)(s)
     }
-    fn range<T: FromStr>(s: &str) -> IResult<&str, Range<T>> {
+    fn range<T: FromStr>(s: &str) -> IResult<&str, RangeInclusive<T>> {
         let (s, (min, max)) = separated_pair(integer, char('-'), integer)(s)?;
-        Ok((s, Range { min, max }))
+        Ok((s, min..=max))
     }
 }
 
@@ -183,27 +521,9 @@ nearby tickets:
             ),
             Ok((
                 vec![
-                    (
-                        "class",
-                        RangeUnion {
-                            first: Range { min: 1, max: 3 },
-                            second: Range { min: 5, max: 7 }
-                        }
-                    ),
-                    (
-                        "row",
-                        RangeUnion {
-                            first: Range { min: 6, max: 11 },
-                            second: Range { min: 33, max: 44 }
-                        }
-                    ),
-                    (
-                        "seat",
-                        RangeUnion {
-                            first: Range { min: 13, max: 40 },
-                            second: Range { min: 45, max: 50 }
-                        }
-                    ),
+                    ("class", IntervalSet::new(vec![1..=3, 5..=7])),
+                    ("row", IntervalSet::new(vec![6..=11, 33..=44])),
+                    ("seat", IntervalSet::new(vec![13..=40, 45..=50])),
                 ],
                 vec![7, 1, 14],
                 vec![
@@ -219,23 +539,15 @@ nearby tickets:
     #[test]
     fn example_1() {
         let rules = &[
-            RangeUnion {
-                first: Range { min: 1, max: 3 },
-                second: Range { min: 5, max: 7 },
-            },
-            RangeUnion {
-                first: Range { min: 6, max: 11 },
-                second: Range { min: 33, max: 44 },
-            },
-            RangeUnion {
-                first: Range { min: 13, max: 40 },
-                second: Range { min: 45, max: 50 },
-            },
+            IntervalSet::new(vec![1..=3, 5..=7]),
+            IntervalSet::new(vec![6..=11, 33..=44]),
+            IntervalSet::new(vec![13..=40, 45..=50]),
         ];
-        assert_equal(invalid_fields(&[7, 3, 47], rules), &[]);
-        assert_equal(invalid_fields(&[40, 4, 50], rules), &[4]);
-        assert_equal(invalid_fields(&[55, 2, 20], rules), &[55]);
-        assert_equal(invalid_fields(&[38, 6, 12], rules), &[12]);
+        let valid = IntervalSet::union_all(rules);
+        assert_equal(invalid_fields(&[7, 3, 47], &valid), &[]);
+        assert_equal(invalid_fields(&[40, 4, 50], &valid), &[4]);
+        assert_equal(invalid_fields(&[55, 2, 20], &valid), &[55]);
+        assert_equal(invalid_fields(&[38, 6, 12], &valid), &[12]);
     }
 
     #[test]
@@ -249,18 +561,9 @@ nearby tickets:
             find_fields(
                 &[vec![3, 9, 18], vec![15, 1, 5], vec![5, 14, 9]],
                 &[
-                    RangeUnion {
-                        first: Range { min: 0, max: 1 },
-                        second: Range { min: 4, max: 19 },
-                    },
-                    RangeUnion {
-                        first: Range { min: 0, max: 5 },
-                        second: Range { min: 8, max: 19 },
-                    },
-                    RangeUnion {
-                        first: Range { min: 0, max: 13 },
-                        second: Range { min: 16, max: 19 },
-                    },
+                    IntervalSet::new(vec![0..=1, 4..=19]),
+                    IntervalSet::new(vec![0..=5, 8..=19]),
+                    IntervalSet::new(vec![0..=13, 16..=19]),
                 ]
             ),
             Some(vec![1, 0, 2])
@@ -271,4 +574,213 @@ nearby tickets:
     fn part_2() {
         assert_eq!(include_str!("inputs/day_16").part_2(), 239_727_793_813);
     }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn interval_set() {
+        let set = IntervalSet::new(vec![10..=12u8, 1..=3, 4..=5, 2..=2, 20..=19, 8..=8]);
+        assert_eq!(
+            set.ranges().collect::<Vec<_>>(),
+            vec![1..=5, 8..=8, 10..=12]
+        );
+        assert!(set.contains(&1) && set.contains(&5) && set.contains(&8) && set.contains(&12));
+        assert!(!set.contains(&0) && !set.contains(&6) && !set.contains(&9) && !set.contains(&13));
+
+        let other = IntervalSet::new(vec![4..=9, 12..=15]);
+        assert_eq!(set.union(&other).ranges().collect::<Vec<_>>(), vec![1..=15]);
+        assert_eq!(
+            set.intersection(&other).ranges().collect::<Vec<_>>(),
+            vec![4..=5, 8..=8, 12..=12]
+        );
+        assert_eq!(
+            set.complement().ranges().collect::<Vec<_>>(),
+            vec![0..=0, 6..=7, 9..=9, 13..=255]
+        );
+        assert_eq!(set.complement().complement(), set);
+        assert_eq!(
+            IntervalSet::<u8>::empty()
+                .complement()
+                .ranges()
+                .collect::<Vec<_>>(),
+            vec![0..=255]
+        );
+        assert!(IntervalSet::new(vec![0..=255u8]).complement().is_empty());
+        assert!(IntervalSet::new(vec![250..=255u8, 0..=3]).contains(&255));
+
+        let (rules, _, _) =
+            parsers::input::<u32>("a: 1-2 or 4-5 or 9-9\n\nyour ticket:\n1\n\nnearby tickets:\n1")
+                .unwrap();
+        assert_eq!(
+            rules[0].1.ranges().collect::<Vec<_>>(),
+            vec![1..=2, 4..=5, 9..=9]
+        );
+    }
+
+    #[test]
+    fn report() {
+        let notes = Notes::<u32>::parse(
+            "\
+class: 0-1 or 4-19
+row: 0-5 or 8-19
+seat: 0-13 or 16-19
+
+your ticket:
+11,12,13
+
+nearby tickets:
+3,9,18
+20,1,25
+15,1,5
+5,14,9",
+        )
+        .unwrap();
+        let report = notes.report();
+        assert_eq!(
+            report.statuses,
+            vec![
+                TicketStatus::Valid,
+                TicketStatus::Invalid(vec![20, 25]),
+                TicketStatus::Valid,
+                TicketStatus::Valid
+            ]
+        );
+        assert_eq!(report.error_rate(), 45);
+        assert_eq!(
+            report.candidates,
+            vec![
+                vec!["row"],
+                vec!["class", "row"],
+                vec!["class", "row", "seat"]
+            ]
+        );
+        assert_eq!(report.mapping, Some(vec!["row", "class", "seat"]));
+        assert_eq!(
+            report.yours.as_ref().unwrap().fields().collect::<Vec<_>>(),
+            vec![("row", 11), ("class", 12), ("seat", 13)]
+        );
+        assert_eq!(
+            report.to_string(),
+            "\
+ticket 0: valid
+ticket 1: invalid (20, 25)
+ticket 2: valid
+ticket 3: valid
+column 0: row
+column 1: class, row
+column 2: class, row, seat
+mapping: row, class, seat
+your ticket: row=11, class=12, seat=13"
+        );
+    }
+
+    #[test]
+    fn report_input() {
+        let notes = Notes::<usize>::parse(include_str!("inputs/day_16")).unwrap();
+        let report = notes.report();
+        assert_eq!(report.error_rate(), 23115);
+        assert_eq!(
+            report
+                .yours
+                .unwrap()
+                .with_prefix("departure")
+                .map(|(_, value)| value)
+                .product::<usize>(),
+            239_727_793_813
+        );
+    }
+
+    #[test]
+    fn decode() {
+        let notes = Notes::<u32>::parse(
+            "\
+class: 0-1 or 4-19
+row: 0-5 or 8-19
+seat, back: 0-13 or 16-19
+
+your ticket:
+11,12,13
+
+nearby tickets:
+3,9,18
+20,1,25
+15,1,5
+5,14,9",
+        )
+        .unwrap();
+        let decoded = notes.decode().unwrap();
+        assert_eq!(decoded.columns, vec!["row", "class", "seat, back"]);
+        assert_eq!(decoded.yours.get("class"), Some(12));
+        assert_eq!(decoded.yours.get("nope"), None);
+        assert_eq!(
+            decoded.yours.with_prefix("s").collect::<Vec<_>>(),
+            vec![("seat, back", 13)]
+        );
+        assert_eq!(
+            decoded.yours.fields().collect::<Vec<_>>(),
+            vec![("row", 11), ("class", 12), ("seat, back", 13)]
+        );
+        assert_eq!(
+            decoded.nearby.iter().map(|(ix, _)| *ix).collect::<Vec<_>>(),
+            vec![0, 2, 3]
+        );
+        assert_eq!(decoded.column("row"), Some(vec![11, 3, 15, 5]));
+        assert_eq!(decoded.column("nope"), None);
+        assert_eq!(
+            decoded
+                .filter(|record| record.get("class") == Some(1))
+                .map(|record| record.get("row"))
+                .collect::<Vec<_>>(),
+            vec![Some(15)]
+        );
+        assert_eq!(
+            decoded.to_csv(),
+            "\
+ticket,row,class,\"seat, back\"
+yours,11,12,13
+0,3,9,18
+2,15,1,5
+3,5,14,9
+"
+        );
+
+        let notes = Notes::<u32>::parse(
+            "a: 1-5 or 7-9\nb: 1-5 or 7-9\n\nyour ticket:\n1,2\n\nnearby tickets:\n1,2",
+        )
+        .unwrap();
+        assert_eq!(notes.decode(), None);
+    }
+
+    #[test]
+    fn report_without_valid_tickets() {
+        let notes =
+            Notes::<u32>::parse("a: 1-2 or 4-5\n\nyour ticket:\n1\n\nnearby tickets:\n9").unwrap();
+        let report = notes.report();
+        assert_eq!(report.statuses, vec![TicketStatus::Invalid(vec![9])]);
+        assert_eq!(report.error_rate(), 9);
+        assert_eq!(report.candidates, Vec::<Vec<&str>>::new());
+        assert_eq!(report.mapping, None);
+        assert_eq!(report.yours, None);
+        assert_eq!(notes.decode(), None);
+
+        let notes =
+            Notes::<u32>::parse("a: 1-2 or 4-5\n\nyour ticket:\n1\n\nnearby tickets:\n1,2\n1")
+                .unwrap();
+        assert_eq!(notes.report().mapping, None);
+
+        // Your ticket doesn't fit the resolved columns.
+        let notes =
+            Notes::<u32>::parse("a: 1-2\n\nyour ticket:\n1,2\n\nnearby tickets:\n1").unwrap();
+        let report = notes.report();
+        assert_eq!(report.mapping, Some(vec!["a"]));
+        assert_eq!(report.yours, None);
+        assert_eq!(notes.decode(), None);
+        assert_eq!(
+            find_fields(&[vec![1, 2], vec![1]], &[IntervalSet::new(vec![1..=2])]),
+            None
+        );
+        assert_eq!(
+            find_fields(&[vec![1, 2]], &[IntervalSet::new(vec![1..=2])]),
+            None
+        );
+    }
 }